@@ -0,0 +1,148 @@
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+use nalgebra_glm as glm;
+use glm::Vec2;
+use crate::color::Color;
+
+/// Half the width of a point marker, in pixels
+const MARKER_SIZE: f32 = 5.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 2],
+    color: Color,
+}
+
+/// Draws lines and point markers on top of the scene, in normalized device coordinates.
+/// Like the [`Overlay`](crate::overlay::Overlay), everything is queued every frame and discarded after drawing.
+pub struct Annotations {
+    render_pipeline: wgpu::RenderPipeline,
+    vertices: Vec<LineVertex>,
+    /// Size of a pixel in normalized device coordinates
+    pixel: Vec2,
+}
+
+impl Annotations {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("Annotation shader"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("annotation.wgsl"))),
+            }
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Annotation pipeline layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let vertex_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                //position
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                //color
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+            ]
+        };
+
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Annotation pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[vertex_buf_layout],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        let mut annotations = Self {
+            render_pipeline,
+            vertices: Vec::new(),
+            pixel: Vec2::zeros(),
+        };
+        annotations.set_size(config.width, config.height);
+        annotations
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.pixel = Vec2::new(2.0 / width.max(1) as f32, 2.0 / height.max(1) as f32);
+    }
+
+    pub fn line(&mut self, a: Vec2, b: Vec2, color: Color) {
+        self.vertices.push(LineVertex { position: a.into(), color });
+        self.vertices.push(LineVertex { position: b.into(), color });
+    }
+
+    /// A small cross centred on the given point
+    pub fn marker(&mut self, p: Vec2, color: Color) {
+        let dx = Vec2::new(self.pixel.x, 0.0) * MARKER_SIZE;
+        let dy = Vec2::new(0.0, self.pixel.y) * MARKER_SIZE;
+        self.line(p - dx, p + dx, color);
+        self.line(p - dy, p + dy, color);
+    }
+
+    pub fn draw(&mut self, device: &wgpu::Device, frame: &wgpu::SurfaceTexture, encoder: &mut wgpu::CommandEncoder) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Annotation vertex buffer"),
+                contents: bytemuck::cast_slice(&self.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Annotation render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: None,
+            }
+        );
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+        drop(render_pass);
+        self.vertices.clear();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::model::{RenderTarget, DEPTH_FORMAT};
 use crate::color::Theme;
 
 use std::borrow::Cow;
@@ -9,7 +10,7 @@ pub struct Background {
 }
 
 impl Background {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme ) -> Self {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme, sample_count: u32) -> Self {
         let bg_colors = theme.get_values().get_background_colors();
 
         let shader = device.create_shader_module(
@@ -88,13 +89,16 @@ impl Background {
                 }),
                 primitive: wgpu::PrimitiveState::default(),
                 depth_stencil: Some(wgpu::DepthStencilState{
-                    format: wgpu::TextureFormat::Depth32Float,
+                    format: DEPTH_FORMAT,
                     depth_write_enabled: true,
                     depth_compare: wgpu::CompareFunction::Always,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
-                multisample: wgpu::MultisampleState::default(),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
                 multiview: None,
             }
         );
@@ -105,18 +109,15 @@ impl Background {
         }
     }
 
-    pub fn draw(&self, frame: &wgpu::SurfaceTexture,
-        depth_view: &wgpu::TextureView,
+    pub fn draw(&self, target: &RenderTarget,
         encoder: &mut wgpu::CommandEncoder) -> Result<(), wgpu::SurfaceError> {
-        //create a TextureView with default settings. This controls how the render code interacts with the texture.
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
         {
             let mut render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
                     label: None,
                     color_attachments: &[wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
+                        view: target.color,
+                        resolve_target: target.resolve,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
                             store: true,
@@ -124,12 +125,15 @@ impl Background {
                     }],
                     depth_stencil_attachment: Some (
                         wgpu::RenderPassDepthStencilAttachment {
-                            view: &depth_view,
+                            view: target.depth,
                             depth_ops: Some(wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(0.0),
                                 store: true
                             }),
-                            stencil_ops: None,
+                            stencil_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(0),
+                                store: true
+                            }),
                         }
                     )
                 }
@@ -0,0 +1,235 @@
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::loader::Vertex;
+
+/// Leaves hold at most this many triangles
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::repeat(f32::INFINITY),
+            max: Vec3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_triangle(tri: &[Vec3; 3]) -> Self {
+        let mut aabb = Self::empty();
+        for p in tri {
+            aabb.grow(p);
+        }
+        aabb
+    }
+
+    pub fn grow(&mut self, p: &Vec3) {
+        self.min = self.min.inf(p);
+        self.max = self.max.sup(p);
+    }
+
+    pub fn merge(&mut self, other: &Aabb) {
+        self.min = self.min.inf(&other.min);
+        self.max = self.max.sup(&other.max);
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    fn centroid(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Distance along the ray at which it enters the box, using the slab method.
+    /// `inv_direction` is the componentwise reciprocal of the ray direction.
+    fn ray_entry(&self, origin: &Vec3, inv_direction: &Vec3, max_distance: f32) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = max_distance;
+        for i in 0..3 {
+            let t0 = (self.min[i] - origin[i]) * inv_direction[i];
+            let t1 = (self.max[i] - origin[i]) * inv_direction[i];
+            //NaN from 0 * inf (ray parallel to and touching a slab) is ignored by min/max
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far { Some(near) } else { None }
+    }
+}
+
+/// Nearest intersection found by [`Bvh::cast_ray`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    pub triangle: u32,
+    /// Distance along the ray, in units of the ray direction's length
+    pub distance: f32,
+}
+
+/// Möller-Trumbore ray-triangle intersection. Both sides of the triangle are hit.
+pub fn ray_triangle(origin: &Vec3, direction: &Vec3, tri: &[Vec3; 3]) -> Option<f32> {
+    let e1 = tri[1] - tri[0];
+    let e2 = tri[2] - tri[0];
+    let p = direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < f32::EPSILON * e1.magnitude() * e2.magnitude() * direction.magnitude() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - tri[0];
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&e1);
+    let v = direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(e2.dot(&q) * inv_det)
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    /// Leaf: index of the first entry in `Bvh::order`. Interior: index of the left child, the right one follows it.
+    first: u32,
+    /// Number of triangles in a leaf, 0 for interior nodes
+    count: u32,
+}
+
+/// Bounding volume hierarchy over the triangles of an indexed mesh.
+/// Built top-down by splitting at the median centroid along the longest axis.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Triangle indices, ordered so that every leaf refers to a contiguous run
+    order: Vec<u32>,
+    /// Triangle corner positions, in the original triangle order
+    triangles: Vec<[Vec3; 3]>,
+}
+
+impl Bvh {
+    pub fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let triangles: Vec<[Vec3; 3]> = indices.chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|i| Vec3::from(vertices[t[i] as usize].pos)))
+            .collect();
+        let boxes: Vec<Aabb> = triangles.iter().map(Aabb::from_triangle).collect();
+        let centroids: Vec<Vec3> = boxes.iter().map(Aabb::centroid).collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * triangles.len() / MAX_LEAF_SIZE + 1),
+            order: (0..triangles.len() as u32).collect(),
+            triangles,
+        };
+        bvh.nodes.push(Node { bounds: Aabb::empty(), first: 0, count: 0 });
+        if !bvh.triangles.is_empty() {
+            bvh.build(0, 0, bvh.triangles.len(), &boxes, &centroids);
+        }
+        bvh
+    }
+
+    pub fn triangle(&self, idx: u32) -> &[Vec3; 3] {
+        &self.triangles[idx as usize]
+    }
+
+    fn build(&mut self, node: usize, start: usize, end: usize, boxes: &[Aabb], centroids: &[Vec3]) {
+        let mut bounds = Aabb::empty();
+        let mut centre_bounds = Aabb::empty();
+        for t in &self.order[start..end] {
+            bounds.merge(&boxes[*t as usize]);
+            centre_bounds.grow(&centroids[*t as usize]);
+        }
+        self.nodes[node].bounds = bounds;
+
+        let extent = centre_bounds.max - centre_bounds.min;
+        if end - start <= MAX_LEAF_SIZE || extent.max() <= 0.0 {
+            self.nodes[node].first = start as u32;
+            self.nodes[node].count = (end - start) as u32;
+            return;
+        }
+
+        let axis = extent.imax();
+        let mid = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            centroids[*a as usize][axis].total_cmp(&centroids[*b as usize][axis])
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(Node { bounds: Aabb::empty(), first: 0, count: 0 });
+        self.nodes.push(Node { bounds: Aabb::empty(), first: 0, count: 0 });
+        self.nodes[node].first = left as u32;
+        self.build(left, start, mid, boxes, centroids);
+        self.build(left + 1, mid, end, boxes, centroids);
+    }
+
+    /// Finds the nearest triangle hit by the ray further than `min_distance` along it,
+    /// ignoring triangles for which `skip` returns true
+    pub fn cast_ray<F: Fn(u32) -> bool>(&self, origin: &Vec3, direction: &Vec3, min_distance: f32, skip: F) -> Option<Hit> {
+        if self.triangles.is_empty() {
+            return None;
+        }
+        let inv_direction = direction.map(|d| 1.0 / d);
+        let mut best: Option<Hit> = None;
+        let mut stack = vec![0usize];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            let limit = best.map_or(f32::INFINITY, |h| h.distance);
+            if node.bounds.ray_entry(origin, &inv_direction, limit).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for t in &self.order[node.first as usize..(node.first + node.count) as usize] {
+                    if skip(*t) {
+                        continue;
+                    }
+                    if let Some(d) = ray_triangle(origin, direction, &self.triangles[*t as usize]) {
+                        if d > min_distance && d < best.map_or(f32::INFINITY, |h| h.distance) {
+                            best = Some(Hit { triangle: *t, distance: d });
+                        }
+                    }
+                }
+            } else {
+                //visit the nearer child first so that the other one is more likely to be pruned
+                let (a, b) = (node.first as usize, node.first as usize + 1);
+                let da = self.nodes[a].bounds.ray_entry(origin, &inv_direction, limit);
+                let db = self.nodes[b].bounds.ray_entry(origin, &inv_direction, limit);
+                match (da, db) {
+                    (Some(da), Some(db)) if da < db => stack.extend([b, a]),
+                    (Some(_), Some(_)) => stack.extend([a, b]),
+                    (Some(_), None) => stack.push(a),
+                    (None, Some(_)) => stack.push(b),
+                    (None, None) => (),
+                }
+            }
+        }
+        best
+    }
+
+    /// Calls `f` with every triangle whose bounding box overlaps `aabb`
+    pub fn query<F: FnMut(u32)>(&self, aabb: &Aabb, mut f: F) {
+        if self.triangles.is_empty() {
+            return;
+        }
+        let mut stack = vec![0usize];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node.bounds.overlaps(aabb) {
+                continue;
+            }
+            if node.count > 0 {
+                for t in &self.order[node.first as usize..(node.first + node.count) as usize] {
+                    if Aabb::from_triangle(&self.triangles[*t as usize]).overlaps(aabb) {
+                        f(*t);
+                    }
+                }
+            } else {
+                stack.push(node.first as usize);
+                stack.push(node.first as usize + 1);
+            }
+        }
+    }
+}
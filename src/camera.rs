@@ -1,41 +1,94 @@
+use std::time::Instant;
 use nalgebra_glm as glm;
-use glm::{Vec2, Vec3, Vec4, Mat4, Quat};
+use glm::{Vec2, Vec3, Vec4, Mat3, Mat4, Quat};
 use winit::event::MouseButton;
 use crate::loader::{ModelBounds};
+use crate::units::BuildDirection;
+
+/// Seconds the camera takes to move to a new view
+const TRANSITION_TIME: f32 = 0.3;
+
+/// Vertical field of view of the perspective projection, in degrees, unless configured
+pub const DEFAULT_FIELD_OF_VIEW: f32 = 40.0;
+
+/// Depth kept in front of and behind the model, in multiples of its bounding radius, so that the
+/// grid and build volume around it aren't clipped
+const DEPTH_MARGIN: f32 = 50.0;
+
+/// Closest the perspective camera gets to the view centre, where the model is about a unit across.
+/// Dollying in further pushes the view centre ahead of the camera.
+const MIN_DISTANCE: f32 = 0.05;
+
+/// Near plane of the perspective projection never comes closer than this fraction of the distance
+/// to the view centre, which keeps the depth buffer precise
+const NEAR_FRACTION: f32 = 0.01;
 
 #[derive(Copy, Clone, Debug)]
 enum MouseState {
     Unknown,
     Free(Vec2),
-    Rotate(Vec2),
+    /// Cursor position and the point rotated about, if it isn't `center`
+    Rotate(Vec2, Option<Vec3>),
     Pan(Vec2, Vec3),
 }
 
+/// Where the camera looks from, and how closely
+#[derive(Copy, Clone, Debug)]
+struct Pose {
+    orientation: Quat,
+    scale: f32,
+    center: Vec3,
+    distance: f32,
+}
+
+/// A smooth move from one pose to another, e.g. started by [`Camera::turn_to`]
+struct Transition {
+    from: Pose,
+    to: Pose,
+    start: Instant,
+}
+
+/// Orientation that looks at the world from `direction`, with Z up. Looking along Z, Y is up from above
+/// and down from below, as if the camera had tipped over from the front view.
+pub fn view_orientation(direction: &Vec3) -> Quat {
+    let back = direction.normalize();
+    let up = if back.x.abs() < 1e-6 && back.y.abs() < 1e-6 {
+        Vec3::new(0.0, back.z.signum(), 0.0)
+    } else {
+        Vec3::z()
+    };
+    let right = up.cross(&back).normalize();
+    //rows are the view axes in model space, so the matrix takes the model into the view
+    glm::mat3_to_quat(&Mat3::from_rows(&[right.transpose(), back.cross(&right).transpose(), back.transpose()]))
+}
+
 enum Projection {
     Orthographic,
     Perspective,
 }
 
-impl Projection {
-    pub fn get_coefficient(&self) -> f32 {
-        match self {
-            Self::Orthographic => 0.0,
-            Self::Perspective => 0.5,
-        }
-    }
-}
-
 /// Camera implementation. Uses a "virtual trackball" scheme as described
 /// in [this](http://hjemmesider.diku.dk/%7Ekash/papers/DSAGM2002_henriksen.pdf) paper.
 /// Most of the implementation was shamelessly taken from https://github.com/Formlabs/foxtrot
 pub struct Camera {
     width: f32,
     height: f32,
+    /// Rotation from the world, where Z is up, to view space
     orientation: Quat,
+    /// Rotation from the model to the world, turning the model's up axis to Z
+    up: Quat,
     scale: f32,
     center: Vec3,
+    /// Distance from the perspective camera to the view centre after the model transform, which is
+    /// changed instead of `scale` to zoom in perspective
+    distance: f32,
+    /// Vertical field of view of the perspective projection, in radians
+    field_of_view: f32,
+    /// Centre and radius of a sphere around the model, which the clipping planes are fitted to
+    extent: (Vec3, f32),
     mouse: MouseState,
     projection_type: Projection,
+    transition: Option<Transition>,
 }
 
 impl Camera {
@@ -46,29 +99,39 @@ impl Camera {
             scale: 1.0,
             center: Vec3::zeros(),
             orientation: Quat::identity(),
+            up: Quat::identity(),
+            distance: Self::focal_distance(DEFAULT_FIELD_OF_VIEW.to_radians()),
+            field_of_view: DEFAULT_FIELD_OF_VIEW.to_radians(),
+            extent: (Vec3::zeros(), 1.0),
             mouse: MouseState::Unknown,
             projection_type: Projection::Orthographic,
+            transition: None,
         }
     }
 
     pub fn mouse_pressed(&mut self, button: MouseButton) {
         if let MouseState::Free(pos) = &self.mouse {
-            match button {
-                MouseButton::Left => Some(MouseState::Rotate(*pos)),
-                MouseButton::Right => Some(MouseState::Pan(*pos, self.mouse_pos(*pos))),
+            self.transition = None;
+            if let Some(m) = match button {
+                MouseButton::Left => Some(MouseState::Rotate(*pos, None)),
+                MouseButton::Right => Some(MouseState::Pan(*pos, self.focal_point(*pos))),
                 _ => None,
-            }.map(|m| self.mouse = m);
+            } {
+                self.mouse = m;
+            }
         }
     }
 
     pub fn mouse_released(&mut self, button: MouseButton) {
-        match &self.mouse {
-            MouseState::Rotate(pos) if button == MouseButton::Left => 
+        if let Some(m) = match &self.mouse {
+            MouseState::Rotate(pos, ..) if button == MouseButton::Left => 
                 Some(MouseState::Free(*pos)),
             MouseState::Pan(pos, ..) if button == MouseButton::Right => 
                 Some(MouseState::Free(*pos)),
             _ => None,
-        }.map(|m| self.mouse = m);
+        } {
+            self.mouse = m;
+        }
     }
 
     /// Camera transformation matrix
@@ -80,9 +143,197 @@ impl Camera {
     pub fn mat_i(&self) -> Mat4 {
         (self.proj_matrix()*self.view_matrix()*self.model_matrix()).try_inverse().expect("Failed to invert mouse matrix")
     }
-    /// Convert normalized mouse position into 3D
-    pub fn mouse_pos(&self, pos_norm: Vec2) -> Vec3 {
-        (self.mat_i() * Vec4::new(pos_norm.x, pos_norm.y, 0.0, 1.0)).xyz()
+
+    /// Rotation from the world, where Z is up, to view space
+    pub fn orientation(&self) -> Quat {
+        self.orientation
+    }
+
+    /// Rotation from model to view space
+    pub fn model_orientation(&self) -> Quat {
+        self.orientation * self.up
+    }
+
+    /// Rotation from the model to the world, where Z is up
+    pub fn up_rotation(&self) -> Quat {
+        self.up
+    }
+
+    pub fn set_up(&mut self, up: BuildDirection) {
+        self.up = up.up_rotation();
+    }
+
+    /// The pose the camera is moving to, or its current pose if it is still. Moves build on this, so
+    /// that e.g. pressing a key twice quickly turns twice as far.
+    fn target(&self) -> Pose {
+        match &self.transition {
+            Some(transition) => transition.to,
+            None => self.pose(),
+        }
+    }
+
+    fn pose(&self) -> Pose {
+        Pose { orientation: self.orientation, scale: self.scale, center: self.center, distance: self.distance }
+    }
+
+    fn move_to(&mut self, mut to: Pose) {
+        //q and -q are the same rotation, and the one closer to the current orientation is the shorter way round
+        if glm::quat_dot(&self.orientation, &to.orientation) < 0.0 {
+            to.orientation = -to.orientation;
+        }
+        let from = self.pose();
+        self.transition = Some(Transition { from, to, start: Instant::now() });
+    }
+
+    /// Advances a move started by [`turn_to`](Self::turn_to) and the like, easing in and out. Call once per frame.
+    pub fn update(&mut self) {
+        if let Some(Transition { from, to, start }) = &self.transition {
+            let t = (start.elapsed().as_secs_f32() / TRANSITION_TIME).min(1.0);
+            let eased = t * t * (3.0 - 2.0 * t);
+            self.orientation = glm::quat_normalize(&glm::quat_slerp(&from.orientation, &to.orientation, eased));
+            //zooming in at a steady rate feels steady
+            self.scale = from.scale * (to.scale / from.scale).powf(eased);
+            self.center = glm::lerp(&from.center, &to.center, eased);
+            self.distance = from.distance * (to.distance / from.distance).powf(eased);
+            if t >= 1.0 {
+                self.transition = None;
+            }
+        }
+    }
+
+    /// Turn smoothly to a new orientation about the view centre, e.g. one from [`view_orientation`]
+    pub fn turn_to(&mut self, orientation: Quat) {
+        self.move_to(Pose { orientation, ..self.target() });
+    }
+
+    /// Turn smoothly about the view centre, by `yaw` radians about the screen's vertical axis and
+    /// `pitch` radians about its horizontal axis
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let target = self.target();
+        let q = glm::quat_angle_axis(pitch, &Vec3::x()) * glm::quat_angle_axis(yaw, &Vec3::y());
+        self.turn_to(q * target.orientation);
+    }
+
+    /// Pan smoothly by fractions of the window's width and height
+    pub fn pan(&mut self, x: f32, y: f32) {
+        let target = self.target();
+        //normalized device coordinates span 2 across, so convert the offset there and back into the model
+        let offset = self.focal_point(Vec2::new(x * 2.0, y * 2.0)) - self.focal_point(Vec2::zeros());
+        let zoom = self.scale / self.distance * target.distance / target.scale;
+        self.move_to(Pose { center: target.center + offset * zoom, ..target });
+    }
+
+    /// Zoom smoothly into the view centre by a factor, dollying the camera in perspective
+    pub fn zoom(&mut self, factor: f32) {
+        let target = self.target();
+        self.move_to(match self.projection_type {
+            Projection::Orthographic => Pose { scale: target.scale * factor, ..target },
+            Projection::Perspective => Pose { distance: target.distance / factor, ..target },
+        });
+    }
+
+    /// Zoom and pan smoothly so that the model fills the view, keeping the orientation
+    pub fn zoom_to_fit(&mut self, bounds: &ModelBounds) {
+        let (scale, center) = Self::fit(bounds);
+        self.move_to(Pose { scale, center, distance: Self::focal_distance(self.field_of_view), ..self.target() });
+    }
+
+    /// Distance at which the perspective projection shows things at the view centre at the same size
+    /// as the orthographic one
+    fn focal_distance(field_of_view: f32) -> f32 {
+        1.0 / (field_of_view / 2.0).tan()
+    }
+
+    pub fn set_field_of_view(&mut self, degrees: f32) {
+        let ratio = self.distance / Self::focal_distance(self.field_of_view);
+        self.field_of_view = degrees.clamp(1.0, 150.0).to_radians();
+        self.distance = ratio * Self::focal_distance(self.field_of_view);
+    }
+
+    /// Switches between orthographic and perspective projection, returning whether it is now
+    /// perspective. The model stays the same size at the view centre.
+    pub fn toggle_projection(&mut self) -> bool {
+        let target = self.target();
+        self.transition = None;
+        (self.orientation, self.center) = (target.orientation, target.center);
+        let focal_distance = Self::focal_distance(self.field_of_view);
+        //dollying in perspective zooms like scaling does in orthographic
+        self.scale = target.scale * focal_distance / target.distance;
+        self.distance = focal_distance;
+        self.projection_type = match self.projection_type {
+            Projection::Orthographic => Projection::Perspective,
+            Projection::Perspective => Projection::Orthographic,
+        };
+        matches!(self.projection_type, Projection::Perspective)
+    }
+
+    /// Rotate the current drag about a point in model coordinates instead of the view centre
+    pub fn set_pivot(&mut self, pivot: Vec3) {
+        if let MouseState::Rotate(_, p) = &mut self.mouse {
+            *p = Some(pivot);
+        }
+    }
+
+    /// Point the current drag rotates about, if it was set with [`set_pivot`](Self::set_pivot)
+    pub fn pivot(&self) -> Option<Vec3> {
+        match self.mouse {
+            MouseState::Rotate(_, pivot) => pivot,
+            _ => None,
+        }
+    }
+
+    /// Point under a normalized mouse position on the plane through the view centre, facing the camera
+    pub fn focal_point(&self, pos_norm: Vec2) -> Vec3 {
+        let (origin, direction) = self.ray(pos_norm);
+        let normal = self.towards_viewer();
+        origin + direction * (self.center - origin).dot(&normal) / direction.dot(&normal)
+    }
+
+    /// Unit vector in model coordinates pointing out of the screen
+    pub fn towards_viewer(&self) -> Vec3 {
+        glm::quat_rotate_vec3(&glm::quat_conjugate(&self.model_orientation()), &Vec3::z())
+    }
+
+    /// Last known mouse position, normalized to -1...1
+    pub fn cursor(&self) -> Option<Vec2> {
+        match self.mouse {
+            MouseState::Free(pos) | MouseState::Rotate(pos, ..) | MouseState::Pan(pos, ..) => Some(pos),
+            MouseState::Unknown => None,
+        }
+    }
+
+    /// Ray through a normalized screen position in model coordinates, starting on the near clipping
+    /// plane (z = 1, since depth is reversed) and pointing into the screen
+    pub fn ray(&self, pos_norm: Vec2) -> (Vec3, Vec3) {
+        let mat_i = self.mat_i();
+        let unproject = |z: f32| {
+            let p = mat_i * Vec4::new(pos_norm.x, pos_norm.y, z, 1.0);
+            p.xyz() / p.w
+        };
+        let near = unproject(1.0);
+        (near, (unproject(0.0) - near).normalize())
+    }
+
+    /// Normalized device coordinates of a point in model coordinates
+    pub fn to_screen(&self, p: &Vec3) -> Vec3 {
+        let clip = self.mat() * Vec4::new(p.x, p.y, p.z, 1.0);
+        clip.xyz() / clip.w
+    }
+
+    /// Size of a pixel in model units, at the depth of the view centre in perspective
+    pub fn pixel_size(&self) -> f32 {
+        (self.focal_point(Vec2::new(2.0 / self.width, 0.0)) - self.focal_point(Vec2::zeros())).magnitude()
+    }
+
+    /// Change in depth buffer value per model unit along the view direction, at the view centre in perspective
+    pub fn depth_scale(&self) -> f32 {
+        let step = 0.01 / self.scale;
+        (self.to_screen(&(self.center + self.towards_viewer() * step)).z - self.to_screen(&self.center).z) / step
+    }
+
+    /// Change in depth buffer value when moving one pixel's width along the view direction
+    pub fn depth_per_pixel(&self) -> f32 {
+        self.pixel_size() * self.depth_scale()
     }
 
     pub fn mouse_scroll(&mut self, delta: f32) {
@@ -91,34 +342,48 @@ impl Camera {
         }
     }
 
+    /// Zoom by a factor, keeping the point under the cursor where it is. In perspective, the camera dollies in.
     pub fn scale(&mut self, value: f32, pos: Vec2) {
-        let start_pos = self.mouse_pos(pos);
-        self.scale *= value;
-        let end_pos = self.mouse_pos(pos);
-        let delta = start_pos - end_pos;
-        let mut delta_mouse = (self.mat() * delta.to_homogeneous()).xyz();
-        delta_mouse.z = 0.0;
-
-        self.center += (self.mat_i() * delta_mouse.to_homogeneous()).xyz();
+        self.transition = None;
+        let start_pos = self.focal_point(pos);
+        match self.projection_type {
+            Projection::Orthographic => self.scale *= value,
+            Projection::Perspective => {
+                self.distance /= value;
+                if self.distance < MIN_DISTANCE {
+                    //fly on through, taking the view centre along
+                    self.center -= self.towards_viewer() * (MIN_DISTANCE - self.distance) / self.scale;
+                    self.distance = MIN_DISTANCE;
+                }
+            },
+        }
+        //sideways only, since the view centre may have moved ahead
+        let delta = start_pos - self.focal_point(pos);
+        let normal = self.towards_viewer();
+        self.center += delta - normal * delta.dot(&normal);
     }
 
-    /// Make sure the camera view fits the model upon startup
-    pub fn fit_verts(&mut self, bounds: &ModelBounds) {
+    /// Scale and centre at which the model fills the view
+    fn fit(bounds: &ModelBounds) -> (f32, Vec3) {
         let xb = bounds.x;
         let yb = bounds.y;
         let zb = bounds.z;
         let dx = xb.1 - xb.0;
         let dy = yb.1 - yb.0;
         let dz = zb.1 - zb.0;
-        self.scale = (1.0 / dx.max(dy).max(dz)) as f32;
-        self.center = Vec3::new((xb.0 + xb.1) as f32 / 2.0,
-                                (yb.0 + yb.1) as f32 / 2.0,
-                                (zb.0 + zb.1) as f32 / 2.0);
+        (1.0 / dx.max(dy).max(dz), Vec3::new((xb.0 + xb.1) / 2.0,
+                                            (yb.0 + yb.1) / 2.0,
+                                            (zb.0 + zb.1) / 2.0))
+    }
+
+    /// Make sure the camera view fits the model upon startup
+    pub fn fit_verts(&mut self, bounds: &ModelBounds) {
+        (self.scale, self.center) = Self::fit(bounds);
+        self.distance = Self::focal_distance(self.field_of_view);
+        self.extent = (self.center, bounds.diagonal() / 2.0);
         
-        //Because of how stl file axes are laid out by convention, the camera begin looking at the 
-        //model from above. We want to look at in from the front. To do so, we rotate around the x-axis
-        //by 90 degrees.
-        self.orientation = glm::quat_rotate_normalized_axis(&self.orientation, -std::f32::consts::FRAC_PI_2, &Vec3::x_axis());
+        //the world has Z up, so start out looking at the front, from -Y
+        self.orientation = view_orientation(&Vec3::new(0.0, -1.0, 0.0));
     }
 
 
@@ -130,13 +395,19 @@ impl Camera {
 
         match &self.mouse {
             MouseState::Pan(_pos, orig)=> {
-                let current_pos = self.mouse_pos(new_pos);
+                let current_pos = self.focal_point(new_pos);
                 let delta_pos = orig - current_pos;
                 self.center += delta_pos;
             },
-            MouseState::Rotate(pos) => {
+            MouseState::Rotate(pos, pivot) => {
                 let q = glm::quat_rotation(&Camera::project(*pos), &Camera::project(new_pos));
+                let old = self.model_orientation();
                 self.orientation = q * self.orientation;
+                //move the centre so that the pivot stays where it is on screen
+                if let Some(p) = *pivot {
+                    let back = self.model_orientation().conjugate() * old;
+                    self.center = p - glm::quat_rotate_vec3(&back, &(p - self.center));
+                }
             },
             _ => (),
         }
@@ -144,7 +415,7 @@ impl Camera {
         match &mut self.mouse {
             MouseState::Free(pos) 
             | MouseState::Pan(pos,..) 
-            | MouseState::Rotate(pos) => *pos = new_pos,
+            | MouseState::Rotate(pos, ..) => *pos = new_pos,
             MouseState::Unknown => self.mouse = MouseState::Free(new_pos),
         }
     }
@@ -155,36 +426,52 @@ impl Camera {
         self.height = height;
     }
     
-    /// Returns a matrix which compensates for window aspect ratio and clipping
+    /// Moves the camera back from the view centre, looking down -Z
     pub fn view_matrix(&self) -> Mat4 {
-        let i = Mat4::identity();
-        
-        // The Z clipping range is 0-1
-        glm::translate(&i, &Vec3::new(0.0, 0.0, 0.5))*
-        //scale to compensate for aspect ratio and reduce Z scale to improve clipping
-        glm::scale(&i, &Vec3::new(1.0, self.width/ self.height, 0.1))
-
+        glm::translate(&Mat4::identity(), &Vec3::new(0.0, 0.0, -self.distance))
     }
 
     pub fn model_matrix(&self) -> Mat4 {
         let i = Mat4::identity();
 
         glm::scale(&i, &Vec3::new(self.scale, self.scale, self.scale)) 
-        * glm::quat_to_mat4(&self.orientation)*glm::translate(&i, &-self.center)
+        * glm::quat_to_mat4(&self.model_orientation())*glm::translate(&i, &-self.center)
     }
 
+    /// Near and far clipping distances from the camera, fitted around the model
+    fn clip_range(&self) -> (f32, f32) {
+        let (center, radius) = self.extent;
+        let depth = self.distance - (self.model_matrix() * Vec4::new(center.x, center.y, center.z, 1.0)).z;
+        let margin = radius * self.scale * DEPTH_MARGIN;
+        match self.projection_type {
+            Projection::Orthographic => (depth - margin, depth + margin),
+            Projection::Perspective => ((depth - margin).max(self.distance * NEAR_FRACTION), depth + margin),
+        }
+    }
+
+    /// Projects from the camera with reversed depth, 1 on the near plane and 0 on the far one. The
+    /// shorter side of the window spans two units at the view centre.
     pub fn proj_matrix(&self) -> Mat4 {
-        const ROW: usize = 4;
-        let mut mat = Mat4::identity();
         let aspect_ratio = self.width/self.height;
-        if aspect_ratio > 1.0 {
-            mat[0] = 1.0/aspect_ratio;
-        } else {
-            mat[ROW*1+1] = aspect_ratio;
+        let (sx, sy) = if aspect_ratio > 1.0 { (1.0/aspect_ratio, 1.0) } else { (1.0, aspect_ratio) };
+        let (near, far) = self.clip_range();
+        match self.projection_type {
+            Projection::Orthographic => Mat4::new(
+                sx, 0.0, 0.0, 0.0,
+                0.0, sy, 0.0, 0.0,
+                0.0, 0.0, 1.0 / (far - near), far / (far - near),
+                0.0, 0.0, 0.0, 1.0,
+            ),
+            Projection::Perspective => {
+                let f = Self::focal_distance(self.field_of_view);
+                Mat4::new(
+                    sx * f, 0.0, 0.0, 0.0,
+                    0.0, sy * f, 0.0, 0.0,
+                    0.0, 0.0, near / (far - near), near * far / (far - near),
+                    0.0, 0.0, -1.0, 0.0,
+                )
+            },
         }
-        mat[ROW*2+2] = self.scale / 2.0;
-        mat[ROW*2+3] = self.projection_type.get_coefficient();
-        mat
     }
 
     ///Holroyd's mapping blends a sphere and a hyperbola to eliminate discontinuities near the boundaries.
@@ -205,4 +492,69 @@ impl Camera {
         }
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use nalgebra_glm as glm;
+    use glm::{Vec2, Vec3};
+    use crate::loader::ModelBounds;
+    use super::Camera;
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new(800.0, 600.0);
+        camera.fit_verts(&ModelBounds { x: (0.0, 20.0), y: (-10.0, 10.0), z: (5.0, 25.0) });
+        camera
+    }
+
+    /// Width and height in pixels of a square on the screen plane through the view centre
+    fn square_pixels(camera: &Camera) -> (f32, f32) {
+        let c = camera.to_screen(&camera.center);
+        let x = camera.to_screen(&(camera.center + Vec3::new(1.0, 0.0, 0.0)));
+        let z = camera.to_screen(&(camera.center + Vec3::new(0.0, 0.0, 1.0)));
+        ((x.x - c.x) * 400.0, (z.y - c.y) * 300.0)
+    }
+
+    #[test]
+    fn test_projection() {
+        let mut camera = camera();
+        for perspective in [false, true] {
+            //the front view shows squares facing the camera square, whatever the aspect ratio
+            let (w, h) = square_pixels(&camera);
+            assert!((w - h).abs() < 1e-3 * w, "{} × {}", w, h);
+
+            //the model lies between the clipping planes, with the nearer corners deeper
+            let front = camera.to_screen(&Vec3::new(0.0, -10.0, 5.0));
+            let back = camera.to_screen(&Vec3::new(0.0, 10.0, 5.0));
+            assert!(back.z > 0.0 && front.z < 1.0 && front.z > back.z, "{:?} {:?}", front, back);
+
+            //rays start on the near plane and pass through the point they were cast at
+            let p = Vec3::new(3.0, -2.0, 9.0);
+            let (origin, direction) = camera.ray(camera.to_screen(&p).xy());
+            assert!((p - origin).normalize().dot(&direction) > 0.9999);
+
+            //switching projection keeps the size at the view centre
+            let size = square_pixels(&camera).0;
+            assert_eq!(camera.toggle_projection(), !perspective);
+            assert!((square_pixels(&camera).0 - size).abs() < 1e-3 * size);
+        }
+    }
+
+    #[test]
+    fn test_dolly() {
+        let mut camera = camera();
+        camera.toggle_projection();
+        let cursor = Vec2::new(0.3, -0.2);
+        let point = camera.focal_point(cursor);
+        let (w, _) = square_pixels(&camera);
+        camera.scale(2.0, cursor);
+        //the camera moved in rather than scaling the model, keeping the point under the cursor
+        assert!((camera.focal_point(cursor) - point).magnitude() < 1e-3);
+        assert!((square_pixels(&camera).0 - w * 2.0).abs() < 1e-2 * w);
+        assert!((camera.scale - 0.05).abs() < 1e-6);
+
+        //dollying past the view centre takes it along
+        let center = camera.center;
+        camera.scale(1e3, Vec2::zeros());
+        assert!(camera.center.y > center.y);
+    }
+}
@@ -10,6 +10,7 @@ pub struct ColorTheme {
     model_key: Color,
     model_fill: Color,
     model_base: Color,
+    text: Color,
 }
 
 impl ColorTheme {
@@ -21,12 +22,17 @@ impl ColorTheme {
         [self.model_key, self.model_fill, self.model_base].map(ColorTheme::rgb_to_srgb)
     }
 
+    pub fn get_text_color(&self) -> Color {
+        ColorTheme::rgb_to_srgb(self.text)
+    }
+
     /// Approximation of the mapping from RGB to sRGB, which is used by most monitors
     fn rgb_to_srgb(color: Color) -> Color {
         color.map(|val| val.powf(2.2))
     }
 }
 
+#[allow(dead_code)]
 pub enum Theme {
     Light,
     Dark,
@@ -45,6 +51,7 @@ impl Theme {
                     model_key: [0.99, 0.96, 0.89, 1.0],
                     model_fill: [0.93, 0.91, 0.84, 1.0],
                     model_base: [0.0, 0.0, 0.0, 1.0],
+                    text: [0.93, 0.91, 0.84, 1.0],
                 }
             },
             Theme::Light => {
@@ -56,6 +63,7 @@ impl Theme {
                     model_key: [0.41, 0.47, 0.52, 1.0],
                     model_fill: [0.6, 0.65, 0.69, 1.0],
                     model_base: [0.0, 0.0, 0.0, 1.0],
+                    text: [0.10, 0.12, 0.16, 1.0],
                 }
             },
             Theme::Solarized => {
@@ -67,6 +75,7 @@ impl Theme {
                     model_key: [0.99, 0.96, 0.89, 1.0],
                     model_fill: [0.93, 0.91, 0.84, 1.0],
                     model_base: [0.41, 0.48, 0.51, 1.0],
+                    text: [0.58, 0.63, 0.63, 1.0],
                 }
             }
         }
@@ -0,0 +1,166 @@
+use std::f32::consts::PI;
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::loader::{ModelBounds, ModelData};
+use crate::repair::{weld_map, weld_tolerance};
+
+/// Curvature at every vertex of a mesh, in the units of the model (1/mm and 1/mm²)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curvature {
+    /// Positive where the surface is convex, negative where it is concave
+    pub mean: Vec<f32>,
+    /// Positive on domes and dents, negative on saddles
+    pub gaussian: Vec<f32>,
+}
+
+/// Estimates the mean and Gaussian curvature at every vertex, following Meyer et al., "Discrete
+/// Differential-Geometry Operators for Triangulated 2-Manifolds". Mean curvature comes from the cotangent
+/// Laplacian and Gaussian curvature from the angle deficit, both divided by the mixed Voronoi area.
+///
+/// Vertices are welded first, like for the wall thickness. Vertices on an open boundary have no
+/// well-defined curvature and get zero. The mesh is expected to face outwards.
+pub fn vertex_curvature(data: &ModelData) -> Curvature {
+    let welded = weld_map(&data.vertices, weld_tolerance(&ModelBounds::from_vertices(&data.vertices)));
+    let position = |v: u32| Vec3::from(data.vertices[v as usize].pos);
+    let count = data.vertices.len();
+    let mut area = vec![0.0f32; count];
+    let mut angles = vec![0.0f32; count];
+    let mut laplacian = vec![Vec3::zeros(); count];
+    let mut normals = vec![Vec3::zeros(); count];
+    let mut edges: Vec<(u32, u32)> = Vec::with_capacity(data.indices.len());
+
+    for t in data.indices.chunks_exact(3) {
+        let v = [welded[t[0] as usize], welded[t[1] as usize], welded[t[2] as usize]];
+        let p = v.map(position);
+        let face = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        let double_area = face.magnitude();
+        if v[0] == v[1] || v[1] == v[2] || v[2] == v[0] || double_area <= 0.0 {
+            continue;
+        }
+        edges.extend((0..3).map(|i| (v[i].min(v[(i + 1) % 3]), v[i].max(v[(i + 1) % 3]))));
+
+        let mut cot = [0.0; 3];
+        for i in 0..3 {
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            let (a, b) = (p[j] - p[i], p[k] - p[i]);
+            //|a × b| is twice the area for every corner
+            cot[i] = a.dot(&b) / double_area;
+            angles[v[i] as usize] += a.angle(&b);
+            normals[v[i] as usize] += face;
+            //the edge opposite the corner is weighted by its cotangent
+            let edge = p[j] - p[k];
+            laplacian[v[j] as usize] += edge * cot[i];
+            laplacian[v[k] as usize] -= edge * cot[i];
+        }
+
+        //Voronoi areas where they fit inside the triangle, otherwise a share of the triangle
+        let obtuse = (0..3).find(|i| cot[*i] < 0.0);
+        for i in 0..3 {
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            area[v[i] as usize] += match obtuse {
+                None => ((p[i] - p[k]).magnitude_squared() * cot[j] + (p[i] - p[j]).magnitude_squared() * cot[k]) / 8.0,
+                Some(o) if o == i => double_area / 4.0,
+                Some(_) => double_area / 8.0,
+            };
+        }
+    }
+
+    edges.sort_unstable();
+    let mut boundary = vec![false; count];
+    for group in edges.chunk_by(|a, b| a == b) {
+        if group.len() == 1 {
+            boundary[group[0].0 as usize] = true;
+            boundary[group[0].1 as usize] = true;
+        }
+    }
+
+    let at = |v: usize| -> (f32, f32) {
+        if boundary[v] || area[v] <= 0.0 {
+            return (0.0, 0.0);
+        }
+        //the Laplacian is twice the mean curvature times the normal, summed over twice the area
+        let mean = laplacian[v].magnitude() / (4.0 * area[v]);
+        let sign = if laplacian[v].dot(&normals[v]) < 0.0 { -1.0 } else { 1.0 };
+        (mean * sign, (2.0 * PI - angles[v]) / area[v])
+    };
+    let (mean, gaussian) = welded.iter().map(|w| at(*w as usize)).unzip();
+    Curvature { mean, gaussian }
+}
+
+/// A symmetric colour map range covering most of the values, so that a few extreme vertices, e.g.
+/// on sharp edges, don't wash out the rest. Never zero.
+pub fn default_range(values: &[f32]) -> f32 {
+    let mut magnitudes: Vec<f32> = values.iter().map(|v| v.abs()).filter(|v| v.is_finite()).collect();
+    if magnitudes.is_empty() {
+        return 1.0;
+    }
+    let index = (magnitudes.len() - 1) * 9 / 10;
+    let (_, range, _) = magnitudes.select_nth_unstable_by(index, f32::total_cmp);
+    if *range > 0.0 { *range } else { 1.0 }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use std::time::SystemTime;
+    use crate::loader::{Loader, ModelBounds, ModelData, Vertex};
+    use super::{default_range, vertex_curvature};
+
+    /// Latitude/longitude sphere facing outwards, with single vertices at the poles
+    fn sphere(radius: f32, rings: u32, segments: u32) -> ModelData {
+        let mut vertices = vec![Vertex { pos: [0.0, 0.0, radius] }];
+        for ring in 1..rings {
+            let theta = ring as f32 / rings as f32 * PI;
+            for segment in 0..segments {
+                let phi = segment as f32 / segments as f32 * 2.0 * PI;
+                vertices.push(Vertex { pos: [radius * theta.sin() * phi.cos(), radius * theta.sin() * phi.sin(), radius * theta.cos()] });
+            }
+        }
+        vertices.push(Vertex { pos: [0.0, 0.0, -radius] });
+        let bottom = vertices.len() as u32 - 1;
+        let at = |ring: u32, segment: u32| 1 + (ring - 1) * segments + segment % segments;
+
+        let mut indices = Vec::new();
+        for s in 0..segments {
+            indices.extend([0, at(1, s), at(1, s + 1)]);
+            indices.extend([bottom, at(rings - 1, s + 1), at(rings - 1, s)]);
+            for ring in 1..rings - 1 {
+                indices.extend([at(ring, s), at(ring + 1, s), at(ring + 1, s + 1)]);
+                indices.extend([at(ring, s), at(ring + 1, s + 1), at(ring, s + 1)]);
+            }
+        }
+        let bounds = ModelBounds::from_vertices(&vertices);
+        ModelData::new(vertices, indices, bounds)
+    }
+
+    #[test]
+    fn test_sphere_curvature() {
+        let mut data = sphere(5.0, 40, 80);
+        let curvature = vertex_curvature(&data);
+        //vertices near the equator, where the grid is closest to regular
+        let ring = 80 * 19 + 1..80 * 21 + 1;
+        for (h, k) in curvature.mean[ring.clone()].iter().zip(&curvature.gaussian[ring]) {
+            assert!((h - 0.2).abs() < 0.2 * 0.02, "mean curvature {}", h);
+            assert!((k - 0.04).abs() < 0.04 * 0.05, "Gaussian curvature {}", k);
+        }
+        assert!((default_range(&curvature.mean) - 0.2).abs() < 0.2 * 0.05);
+
+        //turned inside out, the sphere is concave
+        data.indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2));
+        assert!(vertex_curvature(&data).mean[80 * 20 + 1] < 0.0);
+    }
+
+    #[test]
+    fn test_open_cube_curvature() {
+        let mut data = Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run();
+        let curvature = vertex_curvature(&data);
+        //every vertex is a convex corner
+        assert!(curvature.mean.iter().all(|h| *h > 0.0));
+        assert!(curvature.gaussian.iter().all(|k| *k > 0.0));
+
+        //removing a face leaves its four corners on the boundary
+        data.indices.truncate(30);
+        let open = vertex_curvature(&data);
+        assert_eq!(open.gaussian.iter().filter(|k| **k == 0.0).count(), 4);
+    }
+}
@@ -0,0 +1,110 @@
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::loader::{ModelBounds, ModelData};
+use crate::repair::{weld_map, weld_tolerance};
+
+/// Faces meeting at more than this many degrees are outlined by default
+pub const DEFAULT_FEATURE_ANGLE: f32 = 30.0;
+
+/// Sharp, open and non-manifold edges of a mesh, ready to be drawn as a line list
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FeatureEdges {
+    /// Two indices into `ModelData::vertices` per edge
+    pub lines: Vec<u32>,
+    /// Lowest index of the triangles meeting at each edge, in ascending order, so that the edges of
+    /// a contiguous range of triangles (such as a shell) are contiguous as well
+    pub triangles: Vec<u32>,
+}
+
+impl FeatureEdges {
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// Edges belonging to a range of triangles
+    pub fn range(&self, triangles: std::ops::Range<u32>) -> std::ops::Range<usize> {
+        self.triangles.partition_point(|t| *t < triangles.start)..self.triangles.partition_point(|t| *t < triangles.end)
+    }
+}
+
+/// Finds the edges where the face normals differ by more than `angle` degrees, along with edges that
+/// have only one face or more than two. Vertices are welded first, so split normals don't create edges.
+pub fn feature_edges(data: &ModelData, angle: f32) -> FeatureEdges {
+    let welded = weld_map(&data.vertices, weld_tolerance(&ModelBounds::from_vertices(&data.vertices)));
+    let position = |v: u32| Vec3::from(data.vertices[v as usize].pos);
+    let normals: Vec<Vec3> = data.indices.chunks_exact(3).map(|t| {
+        let n = (position(t[1]) - position(t[0])).cross(&(position(t[2]) - position(t[0])));
+        if n.magnitude() > 0.0 { n.normalize() } else { n }
+    }).collect();
+
+    //(welded edge, triangle, original vertices) for every triangle side, grouped by edge
+    let mut sides: Vec<((u32, u32), u32, [u32; 2])> = data.indices.chunks_exact(3).enumerate()
+        .flat_map(|(t, tri)| (0..3).map(move |i| (t as u32, [tri[i], tri[(i + 1) % 3]])))
+        .map(|(t, [a, b])| {
+            let (wa, wb) = (welded[a as usize], welded[b as usize]);
+            ((wa.min(wb), wa.max(wb)), t, [a, b])
+        })
+        .filter(|(key, ..)| key.0 != key.1)
+        .collect();
+    sides.sort_unstable_by_key(|(key, t, _)| (*key, *t));
+
+    let cos_angle = angle.to_radians().cos();
+    let mut edges: Vec<(u32, [u32; 2])> = Vec::new();
+    for group in sides.chunk_by(|a, b| a.0 == b.0) {
+        let sharp = match group {
+            [(_, t0, _), (_, t1, _)] => {
+                let (n0, n1) = (normals[*t0 as usize], normals[*t1 as usize]);
+                //degenerate faces have no normal to compare
+                n0 != Vec3::zeros() && n1 != Vec3::zeros() && n0.dot(&n1) < cos_angle
+            },
+            _ => true,
+        };
+        if sharp {
+            edges.push((group[0].1, group[0].2));
+        }
+    }
+    edges.sort_unstable_by_key(|(t, _)| *t);
+
+    FeatureEdges {
+        lines: edges.iter().flat_map(|(_, line)| *line).collect(),
+        triangles: edges.iter().map(|(t, _)| *t).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use crate::loader::{Loader, ModelData};
+    use super::{feature_edges, DEFAULT_FEATURE_ANGLE};
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    #[test]
+    fn test_cube_edges() {
+        let data = cube();
+        //the diagonals splitting each side into two triangles are flat
+        let edges = feature_edges(&data, DEFAULT_FEATURE_ANGLE);
+        assert_eq!(edges.len(), 12);
+        assert_eq!(edges.lines.len(), 24);
+        assert!(edges.triangles.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(edges.range(0..12), 0..12);
+
+        assert_eq!(feature_edges(&data, 90.0).len(), 0);
+    }
+
+    #[test]
+    fn test_open_edges() {
+        let mut data = cube();
+        //dropping a triangle opens the diagonal and leaves two cube edges with a single face
+        data.indices.truncate(33);
+        let edges = feature_edges(&data, DEFAULT_FEATURE_ANGLE);
+        assert_eq!(edges.len(), 13);
+        assert_eq!(feature_edges(&data, 90.0).len(), 3);
+    }
+}
@@ -0,0 +1,309 @@
+use std::f32::consts::PI;
+use std::io::{Error, ErrorKind};
+use nalgebra_glm as glm;
+use glm::Vec3;
+use image::codecs::hdr::HdrDecoder;
+
+/// Width of the largest mip of the environment map. Files are resampled to it.
+const RADIANCE_WIDTH: u32 = 1024;
+/// Width of the diffuse irradiance map, which varies slowly enough to be tiny
+const IRRADIANCE_WIDTH: u32 = 32;
+/// Width of the mip the irradiance is convolved from
+const IRRADIANCE_SOURCE_WIDTH: u32 = 64;
+
+/// An equirectangular image, twice as wide as it is high, in linear RGB. The top row looks along +Z.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equirect {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl Equirect {
+    fn from_fn(width: u32, height: u32, radiance: impl Fn(Vec3) -> [f32; 3]) -> Self {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| radiance(direction(width, height, x, y)))
+            .collect();
+        Self { width, height, pixels }
+    }
+
+    /// Averages the source pixels covered by each output pixel, or repeats them when enlarging
+    fn resample(&self, width: u32, height: u32) -> Self {
+        let span = |i: u32, size: u32, source: u32| {
+            let start = (i as u64 * source as u64 / size as u64) as u32;
+            let end = ((i as u64 + 1) * source as u64 / size as u64) as u32;
+            start..end.max(start + 1)
+        };
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let rows = span(y, height, self.height);
+            for x in 0..width {
+                let columns = span(x, width, self.width);
+                let mut sum = [0.0; 3];
+                for sy in rows.clone() {
+                    for sx in columns.clone() {
+                        let p = self.pixels[(sy * self.width + sx) as usize];
+                        (0..3).for_each(|c| sum[c] += p[c]);
+                    }
+                }
+                let n = (rows.len() * columns.len()) as f32;
+                pixels.push(sum.map(|s| s / n));
+            }
+        }
+        Self { width, height, pixels }
+    }
+}
+
+/// Unit direction through the centre of a pixel of an equirectangular image
+fn direction(width: u32, height: u32, x: u32, y: u32) -> Vec3 {
+    let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+    let theta = (y as f32 + 0.5) / height as f32 * PI;
+    Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos())
+}
+
+/// Reads a Radiance RGBE (.hdr) image, with or without run-length encoding
+pub fn parse_hdr(bytes: &[u8]) -> Result<Equirect, String> {
+    let decoder = HdrDecoder::new(bytes).map_err(|e| e.to_string())?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+    Ok(Equirect { width: metadata.width, height: metadata.height, pixels: pixels.into_iter().map(|p| p.0).collect() })
+}
+
+/// Half precision bits of a float, for uploading `Rgba16Float` textures. Rounds towards zero and
+/// flushes values too small for a half to zero.
+pub fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        return sign;
+    }
+    sign | (exponent as u16) << 10 | (mantissa >> 13) as u16
+}
+
+/// Image-based lighting, prefiltered on the CPU: a mip chain of the environment for reflections,
+/// which gets blurrier with roughness, and the cosine-weighted irradiance for diffuse light
+pub struct Environment {
+    pub mips: Vec<Equirect>,
+    pub irradiance: Equirect,
+}
+
+impl Environment {
+    pub fn new(image: &Equirect) -> Self {
+        let mut mips = vec![image.resample(RADIANCE_WIDTH, RADIANCE_WIDTH / 2)];
+        while mips.last().unwrap().height > 2 {
+            let last = mips.last().unwrap();
+            mips.push(last.resample(last.width / 2, last.height / 2));
+        }
+        let source = mips.iter().find(|m| m.width == IRRADIANCE_SOURCE_WIDTH).unwrap();
+        let irradiance = irradiance(source, IRRADIANCE_WIDTH);
+        Self { mips, irradiance }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let image = parse_hdr(&bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self::new(&image))
+    }
+
+    /// A photo studio: a bright ceiling fading to a darker floor, lit by two soft boxes
+    pub fn studio() -> Self {
+        let soft_box = |d: Vec3, center: Vec3, size: f32, intensity: f32| {
+            intensity * (1.0 - ((1.0 - d.dot(&center.normalize())) / size).clamp(0.0, 1.0)).powi(2)
+        };
+        let image = Equirect::from_fn(256, 128, |d| {
+            let sky = 0.15 + 0.75 * ((d.z + 0.2) / 1.2).clamp(0.0, 1.0);
+            let light = soft_box(d, Vec3::new(1.0, -1.0, 1.0), 0.08, 8.0) + soft_box(d, Vec3::new(-1.0, 0.5, 0.4), 0.05, 4.0);
+            let v = sky + light;
+            [v, v, v * 1.02]
+        });
+        Self::new(&image)
+    }
+}
+
+/// Convolves an environment with the cosine lobe around each direction. The result is divided by π,
+/// so it is the light reflected by a white diffuse surface.
+fn irradiance(source: &Equirect, width: u32) -> Equirect {
+    let height = width / 2;
+    let texel_angle = 2.0 * PI / source.width as f32 * PI / source.height as f32;
+    let samples: Vec<(Vec3, [f32; 3])> = (0..source.height).flat_map(|y| (0..source.width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let d = direction(source.width, source.height, x, y);
+            //texels get smaller towards the poles
+            let solid_angle = texel_angle * (1.0 - d.z * d.z).sqrt();
+            let p = source.pixels[(y * source.width + x) as usize];
+            (d, p.map(|c| c * solid_angle / PI))
+        })
+        .collect();
+    Equirect::from_fn(width, height, |n| {
+        let mut sum = [0.0; 3];
+        for (d, p) in &samples {
+            let cos = n.dot(d);
+            if cos > 0.0 {
+                (0..3).for_each(|c| sum[c] += p[c] * cos);
+            }
+        }
+        sum
+    })
+}
+
+/// The environment as textures, sampled by the model shader
+pub struct EnvironmentMap {
+    radiance: wgpu::Texture,
+    irradiance: wgpu::Texture,
+    pub radiance_view: wgpu::TextureView,
+    pub irradiance_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl EnvironmentMap {
+    /// Creates empty textures the size of the environment, see [`upload`](Self::upload)
+    pub fn new(device: &wgpu::Device, environment: &Environment) -> Self {
+        let texture = |label, image: &Equirect, mip_level_count| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let radiance = texture("Environment radiance texture", &environment.mips[0], environment.mips.len() as u32);
+        let irradiance = texture("Environment irradiance texture", &environment.irradiance, 1);
+        //longitude wraps around, latitude stops at the poles
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label: Some("Environment sampler"),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }
+        );
+        Self {
+            radiance_view: radiance.create_view(&wgpu::TextureViewDescriptor::default()),
+            irradiance_view: irradiance.create_view(&wgpu::TextureViewDescriptor::default()),
+            radiance,
+            irradiance,
+            sampler,
+        }
+    }
+
+    pub fn upload(&self, queue: &wgpu::Queue, environment: &Environment) {
+        let write = |texture, mip_level, image: &Equirect| {
+            let texels: Vec<u16> = image.pixels.iter()
+                .flat_map(|[r, g, b]| [f16_bits(*r), f16_bits(*g), f16_bits(*b), f16_bits(1.0)])
+                .collect();
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&texels),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(image.width * 8),
+                    rows_per_image: std::num::NonZeroU32::new(image.height),
+                },
+                wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        };
+        for (level, mip) in environment.mips.iter().enumerate() {
+            write(&self.radiance, level as u32, mip);
+        }
+        write(&self.irradiance, 0, &environment.irradiance);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{f16_bits, irradiance, parse_hdr, Equirect};
+
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes()
+    }
+
+    #[test]
+    fn test_parse_flat_hdr() {
+        let mut bytes = header(2, 1);
+        //1.0 is 128 with an exponent of 129, 0.25 is 128 with 127
+        bytes.extend([128, 64, 0, 129, 128, 128, 128, 127]);
+        let image = parse_hdr(&bytes).unwrap();
+        assert_eq!(image.pixels, vec![[1.0, 0.5, 0.0], [0.25, 0.25, 0.25]]);
+
+        assert!(parse_hdr(&bytes[..bytes.len() - 1]).is_err());
+        assert!(parse_hdr(b"P6\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_rle_hdr() {
+        let mut bytes = header(8, 1);
+        bytes.extend([2, 2, 0, 8]);
+        //red: a run of eight; green: eight literals; blue: two runs of four; exponent: a run of eight
+        bytes.extend([136, 128]);
+        bytes.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend([132, 0, 132, 128]);
+        bytes.extend([136, 129]);
+        let image = parse_hdr(&bytes).unwrap();
+        assert_eq!(image.width, 8);
+        assert_eq!(image.pixels[0], [1.0, 0.0, 0.0]);
+        assert_eq!(image.pixels[7], [1.0, 112.0 / 128.0, 1.0]);
+
+        //cut off in the exponent channel
+        assert!(parse_hdr(&bytes[..bytes.len() - 1]).is_err());
+        //a run longer than the scanline
+        let mut long_run = header(8, 1);
+        long_run.extend([2, 2, 0, 8, 137, 128]);
+        assert!(parse_hdr(&long_run).is_err());
+    }
+
+    #[test]
+    fn test_malformed_hdr() {
+        assert!(parse_hdr(b"").is_err());
+        assert!(parse_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n").is_err());
+        assert!(parse_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x80\x80\x80\x80").is_err());
+        assert!(parse_hdr(b"#?RADIANCE\n\n-Y one +X 1\n\x80\x80\x80\x80").is_err());
+        //more pixels than the data holds
+        let mut bytes = header(2, 2);
+        bytes.extend([128, 128, 128, 129]);
+        assert!(parse_hdr(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_f16_bits() {
+        assert_eq!(f16_bits(0.0), 0);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(-2.5), 0xc100);
+        assert_eq!(f16_bits(1e6), 0x7c00);
+    }
+
+    #[test]
+    fn test_uniform_irradiance() {
+        //a white sky of radiance 1 lights a white surface to 1 from every direction
+        let sky = Equirect { width: 64, height: 32, pixels: vec![[1.0; 3]; 64 * 32] };
+        let result = irradiance(&sky, 8);
+        for p in &result.pixels {
+            assert!((p[0] - 1.0).abs() < 0.01, "{:?}", p);
+        }
+    }
+}
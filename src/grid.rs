@@ -0,0 +1,334 @@
+use wgpu::util::DeviceExt;
+use nalgebra_glm as glm;
+use crate::camera::Camera;
+use crate::color::Theme;
+use crate::loader::{ModelBounds, ModelData};
+use crate::model::{RenderTarget, DEPTH_FORMAT};
+use crate::settings::{GridMode, RenderSettings};
+
+/// Minor grid lines closer together than this many pixels give way to the next coarser grid
+const MIN_SPACING_PIXELS: f32 = 8.0;
+
+/// Half the size of the infinite grid, in multiples of the model size. It fades out well before its edge.
+const INFINITE_EXTENT: f32 = 20.0;
+
+/// Half the size of the bounded grid without a build plate, in multiples of half the model footprint
+const BOUNDED_MARGIN: f32 = 1.5;
+
+/// Mirrors `GridSettings` in grid.wgsl, padded to a multiple of 16 bytes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUniform {
+    to_clip: [f32; 16],
+    color: [f32; 4],
+    center: [f32; 2],
+    half_size: [f32; 2],
+    spacing: f32,
+    minor: f32,
+    infinite: f32,
+    _padding: f32,
+}
+
+/// Spacing of the minor grid lines, a power of ten millimetres, for a pixel of the given size in
+/// millimetres. Also returns the opacity of the minor lines, which fade in as they move apart so
+/// that zooming doesn't make them pop.
+pub fn grid_spacing(pixel_size: f32) -> (f32, f32) {
+    let min_spacing = MIN_SPACING_PIXELS * pixel_size;
+    let spacing = 10f32.powf(min_spacing.log10().ceil());
+    (spacing, ((spacing / min_spacing - 1.0) / 2.0).min(1.0))
+}
+
+/// The 12 edges of the build volume as line list vertices. It stands on the Z=0 plane, centred under the model.
+fn box_lines(size: [f32; 3], bounds: &ModelBounds) -> [[f32; 3]; 24] {
+    let center = [(bounds.x.0 + bounds.x.1) / 2.0, (bounds.y.0 + bounds.y.1) / 2.0];
+    let corner = |i: usize| [
+        center[0] + size[0] * ((i & 1) as f32 - 0.5),
+        center[1] + size[1] * ((i >> 1 & 1) as f32 - 0.5),
+        size[2] * (i >> 2 & 1) as f32,
+    ];
+    let mut lines = [[0.0; 3]; 24];
+    //corners differing in one bit of their index share an edge
+    let edges = (0..8).flat_map(|i| [1, 2, 4].map(|bit| (i, i | bit))).filter(|(a, b)| a != b);
+    for (n, (a, b)) in edges.enumerate() {
+        lines[n * 2] = corner(a);
+        lines[n * 2 + 1] = corner(b);
+    }
+    lines
+}
+
+/// Draws the reference grid on the ground plane, Z=0 in the world where the model's up axis is Z, and
+/// the build volume of the selected printer, after the model so that it hides what is behind it
+pub struct Grid {
+    grid_pipeline: wgpu::RenderPipeline,
+    box_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    box_buffer: wgpu::Buffer,
+    color: [f32; 4],
+}
+
+impl Grid {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("Grid shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("grid.wgsl").into()),
+            }
+        );
+
+        let uniform_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Grid settings buffer"),
+                size: std::mem::size_of::<GridUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+        let box_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Build volume vertex buffer"),
+                contents: bytemuck::cast_slice(&[[0.0f32; 3]; 24]),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("Grid bind group layout"),
+            }
+        );
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("Grid bind group"),
+            }
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Grid pipeline layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        //tested against the model's depth, but not written, so the grid never hides itself
+        let depth_stencil = wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Greater,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let multisample = wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        };
+
+        let grid_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Grid pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_grid",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_grid",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(depth_stencil.clone()),
+                multisample,
+                multiview: None,
+            }
+        );
+
+        let box_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Build volume pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_box",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            //positions
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                        ]
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_box",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: Some(depth_stencil),
+                multisample,
+                multiview: None,
+            }
+        );
+
+        Self {
+            grid_pipeline,
+            box_pipeline,
+            bind_group,
+            uniform_buffer,
+            box_buffer,
+            color: theme.get_values().get_text_color(),
+        }
+    }
+
+    pub fn draw(&self,
+        camera: &Camera,
+        settings: &RenderSettings,
+        data: &ModelData,
+        target: &RenderTarget,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue) {
+        if settings.grid == GridMode::Off && settings.printer.is_none() {
+            return;
+        }
+        //everything is laid out in the world and turned into the model by the shaders
+        let up = camera.up_rotation();
+        let bounds = &data.bounds.rotated(&up);
+        let build_volume = settings.printer.map(|printer| printer.build_volume().map(|l| data.units.millimetres_to_units(l)));
+        let center = [(bounds.x.0 + bounds.x.1) / 2.0, (bounds.y.0 + bounds.y.1) / 2.0];
+        let half_size = match (settings.grid, build_volume) {
+            (GridMode::Bounded, Some([w, d, _])) => [w / 2.0, d / 2.0],
+            (GridMode::Bounded, None) => {
+                let half = (bounds.x.1 - bounds.x.0).max(bounds.y.1 - bounds.y.0) / 2.0 * BOUNDED_MARGIN;
+                [half, half]
+            },
+            _ => [bounds.diagonal() * INFINITE_EXTENT; 2],
+        };
+        //the grid steps by powers of ten millimetres whatever units the model is in
+        let units = data.units;
+        let (spacing, minor) = grid_spacing(camera.pixel_size() * units.millimetres());
+        let spacing = units.millimetres_to_units(spacing);
+        let mut to_clip = [0.0; 16];
+        to_clip.copy_from_slice((camera.mat() * glm::quat_to_mat4(&glm::quat_conjugate(&up))).as_slice());
+        let uniform = GridUniform {
+            to_clip,
+            color: self.color,
+            center,
+            half_size,
+            spacing,
+            minor,
+            infinite: if settings.grid == GridMode::Infinite { 1.0 } else { 0.0 },
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        if let Some(size) = build_volume {
+            queue.write_buffer(&self.box_buffer, 0, bytemuck::cast_slice(&box_lines(size, bounds)));
+        }
+
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Grid render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target.color,
+                    resolve_target: target.resolve,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: Some (
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: target.depth,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true
+                        }),
+                    }
+                )
+            }
+        );
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        if settings.grid != GridMode::Off {
+            render_pass.set_pipeline(&self.grid_pipeline);
+            render_pass.draw(0..6, 0..1);
+        }
+        if settings.printer.is_some() {
+            render_pass.set_pipeline(&self.box_pipeline);
+            render_pass.set_vertex_buffer(0, self.box_buffer.slice(..));
+            render_pass.draw(0..24, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::loader::ModelBounds;
+    use super::{box_lines, grid_spacing};
+
+    #[test]
+    fn test_grid_spacing() {
+        //8 pixels of 0.1 mm need 1 mm lines, which have only just grown large enough to show
+        let (spacing, minor) = grid_spacing(0.1);
+        assert!((spacing - 1.0).abs() < 1e-6);
+        assert!(minor < 0.2);
+        let (spacing, minor) = grid_spacing(0.3);
+        assert!((spacing - 10.0).abs() < 1e-5);
+        assert_eq!(minor, 1.0);
+    }
+
+    #[test]
+    fn test_box_lines() {
+        let bounds = ModelBounds { x: (0.0, 20.0), y: (-10.0, 10.0), z: (5.0, 15.0) };
+        let lines = box_lines([100.0, 50.0, 30.0], &bounds);
+        //every edge runs along one axis, and is as long as the volume in that direction
+        for edge in lines.chunks_exact(2) {
+            let d: Vec<f32> = (0..3).map(|i| (edge[1][i] - edge[0][i]).abs()).filter(|d| *d > 0.0).collect();
+            assert_eq!(d.len(), 1);
+            assert!([100.0, 50.0, 30.0].contains(&d[0]));
+        }
+        assert!(lines.iter().all(|p| p[0] >= -40.0 && p[0] <= 60.0 && p[2] >= 0.0));
+    }
+}
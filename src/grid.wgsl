@@ -0,0 +1,76 @@
+//Reference grid on the Z=0 plane and the wire box of the build volume, depth tested against the model.
+
+struct GridSettings {
+    to_clip: mat4x4<f32>;
+    color: vec4<f32>;
+    center: vec2<f32>;
+    half_size: vec2<f32>;
+    //minor line spacing in model units, every tenth line is a major one
+    spacing: f32;
+    //opacity of the minor lines, which fade out as they get too close together
+    minor: f32;
+    //1 to fade the grid out towards its edges, so that it looks infinite
+    infinite: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> grid: GridSettings;
+
+struct GridOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] plane: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_grid([[builtin(vertex_index)]] index: u32) -> GridOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let plane = grid.center + corners[index] * grid.half_size;
+    var out: GridOutput;
+    out.position = grid.to_clip * vec4<f32>(plane, 0.0, 1.0);
+    out.plane = plane;
+    return out;
+}
+
+//how much of the fragment lies on a line about a pixel wide, for lines at multiples of the spacing
+fn line_coverage(plane: vec2<f32>, spacing: f32) -> f32 {
+    let p = plane / spacing;
+    let distance = abs(fract(p - 0.5) - 0.5) / fwidth(p);
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_grid(in: GridOutput) -> [[location(0)]] vec4<f32> {
+    let minor = line_coverage(in.plane, grid.spacing) * grid.minor * 0.3;
+    let major = line_coverage(in.plane, grid.spacing * 10.0) * 0.6;
+    var color = grid.color.xyz;
+    var alpha = max(minor, major);
+    //the X and Y axes in red and green
+    let axis = abs(in.plane) / fwidth(in.plane);
+    if (axis.y < 1.5) {
+        color = vec3<f32>(0.8, 0.1, 0.1);
+        alpha = 0.9;
+    } else if (axis.x < 1.5) {
+        color = vec3<f32>(0.1, 0.6, 0.1);
+        alpha = 0.9;
+    }
+    if (grid.infinite > 0.0) {
+        alpha = alpha * (1.0 - smoothStep(0.3, 1.0, length(in.plane - grid.center) / grid.half_size.x));
+    }
+    if (alpha < 0.01) {
+        discard;
+    }
+    return vec4<f32>(color, alpha * grid.color.w);
+}
+
+[[stage(vertex)]]
+fn vs_box([[location(0)]] position: vec3<f32>) -> [[builtin(position)]] vec4<f32> {
+    return grid.to_clip * vec4<f32>(position, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_box() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(grid.color.xyz, 0.8);
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use crate::loader::ModelData;
+use crate::mass::{mass_properties, MassProperties};
+use crate::units::{BuildDirection, Units};
+
+/// Cubic millimetres per cubic centimetre
+const MM3_PER_CM3: f64 = 1000.0;
+
+/// Summary of a model, shown in the viewer's info overlay and printed by `hustl info`.
+/// Lengths are in the model's units and `density` is given in g/cm³, so mass is in grams and the
+/// inertia tensor in grams times the units squared.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModelInfo {
+    pub triangles: usize,
+    pub vertices: usize,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub size: [f32; 3],
+    pub units: Units,
+    pub up: BuildDirection,
+    pub density: f64,
+    pub properties: MassProperties,
+}
+
+impl ModelInfo {
+    pub fn new(data: &ModelData, density: f64) -> Self {
+        let b = &data.bounds;
+        let cm3_per_unit3 = (data.units.millimetres() as f64).powi(3) / MM3_PER_CM3;
+        Self {
+            triangles: data.indices.len() / 3,
+            vertices: data.vertices.len(),
+            min: [b.x.0, b.y.0, b.z.0],
+            max: [b.x.1, b.y.1, b.z.1],
+            size: [b.x.1 - b.x.0, b.y.1 - b.y.0, b.z.1 - b.z.0],
+            units: data.units,
+            up: data.up,
+            density,
+            properties: mass_properties(data, density * cm3_per_unit3),
+        }
+    }
+}
+
+impl fmt::Display for ModelInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = &self.properties;
+        let i = &p.inertia;
+        writeln!(f, "Triangles: {}", self.triangles)?;
+        writeln!(f, "Vertices:  {}", self.vertices)?;
+        let u = self.units;
+        writeln!(f, "Size:      {:.3} x {:.3} x {:.3} {}", self.size[0], self.size[1], self.size[2], u)?;
+        writeln!(f, "Up:        {}", self.up)?;
+        writeln!(f, "Volume:    {:.3} {}³", p.volume, u)?;
+        writeln!(f, "Area:      {:.3} {}²", p.area, u)?;
+        writeln!(f, "Centroid:  ({:.3}, {:.3}, {:.3})", p.centroid[0], p.centroid[1], p.centroid[2])?;
+        writeln!(f, "Mass:      {:.3} g at {} g/cm³", p.mass, self.density)?;
+        writeln!(f, "Inertia (g·{}²):", u)?;
+        writeln!(f, "           [{:.4e} {:.4e} {:.4e}]", i[0][0], i[0][1], i[0][2])?;
+        writeln!(f, "           [{:.4e} {:.4e} {:.4e}]", i[1][0], i[1][1], i[1][2])?;
+        write!(f, "           [{:.4e} {:.4e} {:.4e}]", i[2][0], i[2][1], i[2][2])
+    }
+}
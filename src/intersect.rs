@@ -0,0 +1,218 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, Vec3};
+use crate::bvh::{Aabb, Bvh};
+use crate::loader::{worker_count, ModelBounds, ModelData};
+use crate::repair::{weld_map, weld_tolerance};
+
+/// Plane distances smaller than this fraction of the triangle size are treated as touching the plane
+const PLANE_EPSILON: f64 = 1e-9;
+
+/// Pairs of triangles that cut through each other
+#[derive(Debug, Clone, Default)]
+pub struct SelfIntersections {
+    /// Triangle index pairs, with the lower index first
+    pub pairs: Vec<(u32, u32)>,
+    /// Every triangle that appears in at least one pair, sorted
+    pub faces: Vec<u32>,
+}
+
+/// Finds every pair of triangles that intersect, ignoring neighbours that share an edge or a vertex.
+/// The triangles are split evenly between worker threads, like the loader does, and each worker queries
+/// a shared BVH with the bounding box of each of its triangles.
+pub fn find_self_intersections(data: &ModelData, max_workers: Option<usize>) -> SelfIntersections {
+    let corners = welded_triangles(data);
+    let bvh = Bvh::new(&data.vertices, &data.indices);
+    let num_triangles = corners.len();
+    let num_workers = worker_count(max_workers).max(1);
+    let triangles_per_worker = num_triangles / num_workers + 1;
+
+    let bvh = &bvh;
+    let corners = corners.as_slice();
+    let mut pairs: Vec<(u32, u32)> = crossbeam::scope(move |s| {
+        let handles: Vec<crossbeam::thread::ScopedJoinHandle<_>> = (0..num_workers).map(|n| {
+            let start = (n * triangles_per_worker).min(num_triangles);
+            let end = ((n + 1) * triangles_per_worker).min(num_triangles);
+            s.spawn(move |_| {
+                let mut found = Vec::new();
+                for i in start as u32..end as u32 {
+                    let tri = bvh.triangle(i);
+                    bvh.query(&Aabb::from_triangle(tri), |j| {
+                        if j > i && !shares_vertex(&corners[i as usize], &corners[j as usize])
+                            && triangles_intersect(tri, bvh.triangle(j)) {
+                            found.push((i, j));
+                        }
+                    });
+                }
+                found
+            })
+        }).collect();
+
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    }).unwrap();
+    pairs.sort_unstable();
+
+    let mut faces: Vec<u32> = pairs.iter().flat_map(|(a, b)| [*a, *b]).collect();
+    faces.sort_unstable();
+    faces.dedup();
+
+    SelfIntersections { pairs, faces }
+}
+
+/// Triangle corners as welded vertex ids, so that neighbours on either side of a loader seam, or
+/// separated by rounding noise, are recognised.
+fn welded_triangles(data: &ModelData) -> Vec<[u32; 3]> {
+    let welded = weld_map(&data.vertices, weld_tolerance(&ModelBounds::from_vertices(&data.vertices)));
+    data.indices.chunks_exact(3)
+        .map(|t| [welded[t[0] as usize], welded[t[1] as usize], welded[t[2] as usize]])
+        .collect()
+}
+
+fn shares_vertex(a: &[u32; 3], b: &[u32; 3]) -> bool {
+    a.iter().any(|v| b.contains(v))
+}
+
+/// Signed distances of the corners of `tri` to the plane of `other`, snapped to zero when very close
+fn plane_distances(tri: &[DVec3; 3], other: &[DVec3; 3]) -> (DVec3, [f64; 3]) {
+    let normal = (other[1] - other[0]).cross(&(other[2] - other[0]));
+    let size = (other[1] - other[0]).magnitude().max((tri[1] - tri[0]).magnitude());
+    let eps = PLANE_EPSILON * normal.magnitude() * size;
+    let d = tri.map(|p| {
+        let d = normal.dot(&(p - other[0]));
+        if d.abs() < eps { 0.0 } else { d }
+    });
+    (normal, d)
+}
+
+fn same_side(d: &[f64; 3]) -> bool {
+    (d[0] > 0.0 && d[1] > 0.0 && d[2] > 0.0) || (d[0] < 0.0 && d[1] < 0.0 && d[2] < 0.0)
+}
+
+/// Interval where a triangle crosses the line of intersection of both planes, given the projection of
+/// its corners onto that line and their distances to the other plane. Returns `None` if coplanar.
+fn line_interval(p: [f64; 3], d: [f64; 3]) -> Option<(f64, f64)> {
+    //find the corner that lies alone on its side of the plane
+    let lone = if d[0] * d[1] > 0.0 {
+        2
+    } else if d[0] * d[2] > 0.0 {
+        1
+    } else if d[1] * d[2] > 0.0 || d[0] != 0.0 {
+        0
+    } else if d[1] != 0.0 {
+        1
+    } else if d[2] != 0.0 {
+        2
+    } else {
+        return None;
+    };
+    let (i, j) = ((lone + 1) % 3, (lone + 2) % 3);
+    let a = p[i] + (p[lone] - p[i]) * d[i] / (d[i] - d[lone]);
+    let b = p[j] + (p[lone] - p[j]) * d[j] / (d[j] - d[lone]);
+    Some((a.min(b), a.max(b)))
+}
+
+/// Triangle-triangle overlap test after Möller, "A Fast Triangle-Triangle Intersection Test" (1997).
+/// Triangles that merely touch at a point or along an edge are not reported.
+/// The test runs in double precision, since thin, nearly coplanar neighbours are common in dense meshes.
+pub fn triangles_intersect(t1: &[Vec3; 3], t2: &[Vec3; 3]) -> bool {
+    let t1 = &t1.map(glm::convert::<Vec3, DVec3>);
+    let t2 = &t2.map(glm::convert::<Vec3, DVec3>);
+    let (n2, d1) = plane_distances(t1, t2);
+    if same_side(&d1) {
+        return false;
+    }
+    let (n1, d2) = plane_distances(t2, t1);
+    if same_side(&d2) {
+        return false;
+    }
+
+    let direction = n1.cross(&n2);
+    let axis = direction.abs().imax();
+    let i1 = line_interval(t1.map(|p| p[axis]), d1);
+    let i2 = line_interval(t2.map(|p| p[axis]), d2);
+    match (i1, i2) {
+        (Some(a), Some(b)) => {
+            let overlap = a.1.min(b.1) - a.0.max(b.0);
+            let scale = (a.1 - a.0).max(b.1 - b.0);
+            overlap > PLANE_EPSILON * scale
+        },
+        _ => coplanar_intersect(&n1, t1, t2),
+    }
+}
+
+fn coplanar_intersect(normal: &DVec3, t1: &[DVec3; 3], t2: &[DVec3; 3]) -> bool {
+    //drop the axis the triangles are most aligned with
+    let axis = normal.abs().imax();
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let a = t1.map(|p| DVec2::new(p[u], p[v]));
+    let b = t2.map(|p| DVec2::new(p[u], p[v]));
+
+    let cross = |o: DVec2, p: DVec2, q: DVec2| (p.x - o.x) * (q.y - o.y) - (p.y - o.y) * (q.x - o.x);
+    let edges_cross = |p0: DVec2, p1: DVec2, q0: DVec2, q1: DVec2| {
+        let d1 = cross(q0, q1, p0);
+        let d2 = cross(q0, q1, p1);
+        let d3 = cross(p0, p1, q0);
+        let d4 = cross(p0, p1, q1);
+        d1 * d2 < 0.0 && d3 * d4 < 0.0
+    };
+    let contains = |t: &[DVec2; 3], p: DVec2| {
+        let s = [cross(t[0], t[1], p), cross(t[1], t[2], p), cross(t[2], t[0], p)];
+        s.iter().all(|x| *x > 0.0) || s.iter().all(|x| *x < 0.0)
+    };
+
+    for i in 0..3 {
+        for j in 0..3 {
+            if edges_cross(a[i], a[(i + 1) % 3], b[j], b[(j + 1) % 3]) {
+                return true;
+            }
+        }
+    }
+    contains(&b, a[0]) || contains(&a, b[0])
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use nalgebra_glm::Vec3;
+    use crate::loader::{Loader, Vertex};
+    use super::{find_self_intersections, triangles_intersect};
+
+    #[test]
+    fn test_triangle_pairs() {
+        let t1 = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0)];
+        let crossing = [Vec3::new(0.5, 0.5, -1.0), Vec3::new(0.5, 0.5, 1.0), Vec3::new(1.0, 0.0, 1.0)];
+        let above = [Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.5, 0.5, 2.0), Vec3::new(1.0, 0.0, 1.0)];
+        let coplanar = [Vec3::new(0.5, 0.5, 0.0), Vec3::new(3.0, 0.5, 0.0), Vec3::new(0.5, 3.0, 0.0)];
+        let coplanar_apart = [Vec3::new(5.0, 5.0, 0.0), Vec3::new(6.0, 5.0, 0.0), Vec3::new(5.0, 6.0, 0.0)];
+
+        assert!(triangles_intersect(&t1, &crossing));
+        assert!(triangles_intersect(&crossing, &t1));
+        assert!(!triangles_intersect(&t1, &above));
+        assert!(triangles_intersect(&t1, &coplanar));
+        assert!(!triangles_intersect(&t1, &coplanar_apart));
+    }
+
+    #[test]
+    fn test_cube_is_clean() {
+        let data = Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run();
+        let result = find_self_intersections(&data, Some(2));
+        assert!(result.pairs.is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_cubes() {
+        let mut data = Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run();
+        //a second copy shifted by half a side cuts through the first one
+        let offset = data.vertices.len() as u32;
+        let moved: Vec<Vertex> = data.vertices.iter()
+            .map(|v| Vertex { pos: [v.pos[0] + 10.0, v.pos[1] + 10.0, v.pos[2] + 10.0] })
+            .collect();
+        data.vertices.extend(moved);
+        let moved_indices: Vec<u32> = data.indices.iter().map(|i| i + offset).collect();
+        data.indices.extend(moved_indices);
+
+        let result = find_self_intersections(&data, Some(3));
+        assert!(!result.pairs.is_empty());
+        assert!(result.pairs.iter().all(|(a, b)| *a < 12 && *b >= 12));
+        assert!(result.faces.iter().any(|f| *f < 12) && result.faces.iter().any(|f| *f >= 12));
+    }
+}
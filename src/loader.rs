@@ -3,6 +3,8 @@ use ahash::AHashMap;
 use crossbeam::thread::ScopedJoinHandle;
 use std::time::SystemTime;
 use std::thread;
+use crate::units::BuildDirection;
+use crate::units::Units;
 
 
 const BYTES_PER_TRIANGLE: u32 = 50;
@@ -40,6 +42,36 @@ pub struct ModelBounds {
 }
 
 impl ModelBounds {
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let mut bounds = Self::default();
+        for vertex in vertices {
+            bounds.update(vertex);
+        }
+        bounds
+    }
+
+    /// Bounds of the box turned by a rotation, which must be a multiple of a quarter turn about the axes
+    /// for the box to fit exactly, like [`BuildDirection::up_rotation`]
+    pub fn rotated(&self, rotation: &nalgebra_glm::Quat) -> Self {
+        let corners: Vec<Vertex> = (0..8).map(|i| {
+            let corner = nalgebra_glm::Vec3::new(
+                if i & 1 == 0 { self.x.0 } else { self.x.1 },
+                if i & 2 == 0 { self.y.0 } else { self.y.1 },
+                if i & 4 == 0 { self.z.0 } else { self.z.1 },
+            );
+            Vertex { pos: nalgebra_glm::quat_rotate_vec3(rotation, &corner).into() }
+        }).collect();
+        Self::from_vertices(&corners)
+    }
+
+    /// Length of the bounding box diagonal
+    pub fn diagonal(&self) -> f32 {
+        let dx = self.x.1 - self.x.0;
+        let dy = self.y.1 - self.y.0;
+        let dz = self.z.1 - self.z.0;
+        (dx*dx + dy*dy + dz*dz).sqrt()
+    }
+
     fn merge_bounds(&mut self, other: &ModelBounds) {
         self.x.0 = self.x.0.min(other.x.0);
         self.x.1 = self.x.1.max(other.x.1);
@@ -49,7 +81,7 @@ impl ModelBounds {
         self.z.1 = self.z.1.max(other.z.1);
     }
 
-    fn update(&mut self, vertex: &Vertex) {
+    pub fn update(&mut self, vertex: &Vertex) {
         self.x.0 = self.x.0.min(vertex.pos[0]);
         self.x.1 = self.x.1.max(vertex.pos[0]);
         self.y.0 = self.y.0.min(vertex.pos[1]);
@@ -70,10 +102,27 @@ impl Default for ModelBounds {
     }
 }
 
+/// Number of worker threads to use: one per core, optionally capped at `max_workers`
+pub fn worker_count(max_workers: Option<usize>) -> usize {
+    let cores = thread::available_parallelism().expect("Could not query number of cores").get();
+    max_workers.map_or(cores, |max| max.min(cores))
+}
+
 pub struct ModelData {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub bounds: ModelBounds,
+    /// Unit of the coordinates, guessed from the bounds unless given
+    pub units: Units,
+    /// Direction that is up in the model, +Z for STL files by convention
+    pub up: BuildDirection,
+}
+
+impl ModelData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, bounds: ModelBounds) -> Self {
+        let units = Units::detect(&bounds);
+        Self { vertices, indices, bounds, units, up: BuildDirection::PosZ }
+    }
 }
 
 pub struct Loader {
@@ -94,12 +143,8 @@ impl Loader {
         .collect();
 
         let num_triangles = (floats.len()/12) as u32;
-        let num_threads = if let Some(max_workers) = self.max_workers {
-            max_workers.min(thread::available_parallelism().expect("Could not query number of cores").get()) as u32
-        } else {
-            thread::available_parallelism().expect("Could not query number of cores").get() as u32
-        };
-        println!("Number of loaders: {}", num_threads);
+        let num_threads = worker_count(self.max_workers) as u32;
+        eprintln!("Number of loaders: {}", num_threads);
         let triangles_per_thread = num_triangles/num_threads;
 
         let float_slice = floats.as_slice();
@@ -137,7 +182,7 @@ impl Loader {
             panic!("File is too small to be an STL file: ({} < 84 bytes)", bytestream.len());
         }
         let result = match std::str::from_utf8(&bytestream[0..5]) {
-            Ok(header) if header == "solid" => {
+            Ok("solid") => {
                 self.parse_ascii(String::from_utf8_lossy(bytestream.as_slice()).to_string())
             }
             _ => {
@@ -146,7 +191,7 @@ impl Loader {
         };
         let parse_time = SystemTime::now();
         let dt = parse_time.duration_since(self.start_time).expect("Negative parse time calculated?");
-        println!("Time to parse files {:?}", dt);
+        eprintln!("Time to parse files {:?}", dt);
         result
     }
 
@@ -157,12 +202,8 @@ impl Loader {
         let num_triangles = u32::from_le_bytes(bytestream[80..84].try_into().expect("Slice with incorrect length")); 
 
         //TODO: enable multithreading
-        let num_threads = if let Some(max_workers) = self.max_workers {
-            max_workers.min(thread::available_parallelism().expect("Could not query number of cores").get()) as u32
-        } else {
-            thread::available_parallelism().expect("Could not query number of cores").get() as u32
-        };
-        println!("Number of loaders: {}", num_threads);
+        let num_threads = worker_count(self.max_workers) as u32;
+        eprintln!("Number of loaders: {}", num_threads);
         let triangles_per_thread = num_triangles/num_threads;
         let remaining_triangles = num_triangles % num_threads;
         //number of bytes per worker: 50 bytes/triangle * triangles_per_thread
@@ -212,7 +253,7 @@ impl Loader {
             current_index = vertices.len() as u32;
         }
 
-        ModelData { vertices, indices, bounds}
+        ModelData::new(vertices, indices, bounds)
     }
 }
 
@@ -258,7 +299,7 @@ impl Worker {
             }
             //last 2 bytes are the "attribute byte count" and are ignored.
         }
-        ModelData { vertices, indices, bounds }
+        ModelData::new(vertices, indices, bounds)
     }
 
     fn get_binary_vertices_indexed(&mut self, bytes: &[u8], n: u32) -> ModelData {
@@ -280,7 +321,7 @@ impl Worker {
            //last 2 bytes are the "attribute byte count" and are ignored.   
         }
 
-        ModelData { vertices, indices, bounds }
+        ModelData::new(vertices, indices, bounds)
     }
 
     #[allow(dead_code)]
@@ -300,7 +341,7 @@ impl Worker {
             }
         }
 
-        ModelData { vertices, indices, bounds }
+        ModelData::new(vertices, indices, bounds)
     }
 
     fn get_ascii_vertices_indexed(&mut self, floats: &[f32]) -> ModelData {
@@ -320,7 +361,7 @@ impl Worker {
             }
         }
 
-        ModelData { vertices, indices, bounds }
+        ModelData::new(vertices, indices, bounds)
     }
 
     fn get_vertex_index(&mut self, vertex: Vertex, vector: &mut Vec<Vertex>) -> u32 {
@@ -4,8 +4,35 @@ mod camera;
 mod bg;
 mod state;
 mod color;
+mod repair;
+mod writer;
+mod mass;
+mod info;
+mod overlay;
+mod settings;
+mod shells;
+mod bvh;
+mod intersect;
+mod overhang;
+mod thickness;
+mod curvature;
+mod section;
+mod slice;
+mod measure;
+mod picking;
+mod edges;
+mod outline;
+mod ssao;
+mod grid;
+mod units;
+mod viewcube;
+mod xray;
+mod shadow;
+mod environment;
+mod matcap;
+mod annotation;
 
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -13,24 +40,226 @@ use winit::{
 };
 use clap::Parser;
 
-use crate::{loader::Loader, color::Theme};
-use crate::model::Model;
-use crate::state::State;
+use crate::{loader::{Loader, ModelData}, color::Theme};
+use crate::info::ModelInfo;
+use crate::state::{Options, State};
+use crate::section::SectionPlane;
+use crate::units::{BuildDirection, Units};
 
 #[derive(clap::Parser)]
 struct Args {
-    filename: Option<String>
+    #[clap(subcommand)]
+    command: Option<Command>,
+    filename: Option<String>,
+    /// Material density in g/cm³, used for mass properties
+    #[clap(long, global = true, default_value_t = 1.0)]
+    density: f64,
+    /// Units the model is in: mm, cm, m or in. Guessed from its size if not given.
+    #[clap(long, global = true)]
+    units: Option<Units>,
+    /// Axis of the model that points up: x, -x, y, -y, z or -z
+    #[clap(long, global = true, default_value = "z", allow_hyphen_values = true)]
+    up: BuildDirection,
+    /// Rotate about the middle of the view instead of the surface point under the cursor
+    #[clap(long)]
+    orbit_center: bool,
+    /// Anti-aliasing samples per pixel: 1 (off), 2, 4 or 8
+    #[clap(long, default_value_t = model::DEFAULT_SAMPLE_COUNT, parse(try_from_str = model::parse_sample_count))]
+    msaa: u32,
+    /// Ambient occlusion radius in pixels
+    #[clap(long, default_value_t = ssao::DEFAULT_RADIUS)]
+    ao_radius: f32,
+    /// How dark ambient occlusion makes creases, from 0 (not at all) to 1
+    #[clap(long, default_value_t = ssao::DEFAULT_STRENGTH)]
+    ao_strength: f32,
+    /// Radiance HDR (.hdr) environment lighting the physically based materials
+    #[clap(long)]
+    environment: Option<String>,
+    /// Start with a physically based material: plastic, aluminium, resin or steel
+    #[clap(long)]
+    material: Option<settings::Material>,
+    /// PNG matcap to offer along with the built-in ones, and start with. Can be given more than once.
+    #[clap(long, multiple_occurrences = true)]
+    matcap: Vec<String>,
+    /// Reference grid on the ground plane: off, infinite or bounded
+    #[clap(long, default_value_t = settings::GridMode::Off)]
+    grid: settings::GridMode,
+    /// Draw the build volume of a printer: mk4, mini, x1, ender3, s5 or form3
+    #[clap(long)]
+    printer: Option<settings::Printer>,
+    /// Start with a perspective projection rather than orthographic
+    #[clap(long)]
+    perspective: bool,
+    /// Vertical field of view of the perspective projection, in degrees
+    #[clap(long, default_value_t = camera::DEFAULT_FIELD_OF_VIEW)]
+    fov: f32,
 }
 
-async fn run(start_time: SystemTime, filename: Option<String>, event_loop: EventLoop<()>, window: Window) {
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Repair a mesh without opening the viewer and save the result
+    Repair {
+        input: String,
+        output: String,
+    },
+    /// List the disconnected shells in a mesh
+    Shells {
+        input: String,
+        /// Also write every shell to its own STL file next to the input
+        #[clap(long)]
+        export: bool,
+    },
+    /// Count self-intersecting triangle pairs
+    Intersections {
+        input: String,
+    },
+    /// Report the area that needs support when 3D printing
+    Overhangs {
+        input: String,
+        /// Build direction, pointing away from the build plate: x, -x, y, -y, z or -z. Defaults to --up.
+        #[clap(long, allow_hyphen_values = true)]
+        direction: Option<BuildDirection>,
+        /// Faces angled further than this from vertical, in degrees, need support
+        #[clap(long, default_value_t = overhang::DEFAULT_THRESHOLD)]
+        angle: f32,
+    },
+    /// Measure wall thickness and report walls too thin to print
+    Thickness {
+        input: String,
+        /// Thinnest printable wall, in millimetres whatever units the model is in
+        #[clap(long, default_value_t = thickness::DEFAULT_MIN_THICKNESS)]
+        min: f32,
+    },
+    /// Cut the mesh into 2D contours, either with one plane or a stack of layers
+    Slice {
+        input: String,
+        /// Axis the planes are perpendicular to: x, y or z. Defaults to the --up axis.
+        #[clap(long, parse(try_from_str = slice::parse_axis))]
+        axis: Option<usize>,
+        /// Cut with a single plane at this position along the axis instead of slicing the whole model
+        #[clap(long, allow_hyphen_values = true)]
+        at: Option<f32>,
+        /// Distance between planes when slicing the whole model
+        #[clap(long, default_value_t = 0.2)]
+        layer_height: f32,
+        /// Write the contours to an SVG file
+        #[clap(long)]
+        svg: Option<String>,
+        /// Write the contours to a DXF file
+        #[clap(long)]
+        dxf: Option<String>,
+    },
+    /// Print mesh statistics and mass properties
+    Info {
+        input: String,
+        /// Print as JSON
+        #[clap(long)]
+        json: bool,
+    },
+}
+
+/// Loads a model, overriding the detected units if they were given
+fn load(filename: String, start_time: SystemTime, units: Option<Units>, up: BuildDirection) -> ModelData {
+    let mut data = Loader::new(filename, start_time, None).run();
+    if let Some(units) = units {
+        data.units = units;
+    }
+    data.up = up;
+    data
+}
+
+/// Runs a subcommand without creating a window
+fn run_headless(start_time: SystemTime, command: Command, density: f64, units: Option<Units>, up: BuildDirection) {
+    match command {
+        Command::Repair { input, output } => {
+            let mut data = load(input, start_time, units, up);
+            let summary = data.repair();
+            println!("{}", summary);
+            if let Err(e) = writer::write_binary_stl(&output, &data) {
+                eprintln!("Could not write {}: {}", output, e);
+                std::process::exit(1);
+            }
+        },
+        Command::Shells { input, export } => {
+            let mut data = load(input.clone(), start_time, units, up);
+            let (shells, _) = shells::find_shells(&mut data);
+            println!("Shells: {}", shells.len());
+            for (i, shell) in shells.iter().enumerate() {
+                println!("{:>4} {}", i, shell.describe());
+                if export {
+                    let path = shells::export_path(&input, i);
+                    if let Err(e) = shell.export(&data, &path) {
+                        eprintln!("Could not write {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        },
+        Command::Intersections { input } => {
+            let data = load(input, start_time, units, up);
+            //timed separately so that parsing the file isn't counted
+            let check_start = Instant::now();
+            let result = intersect::find_self_intersections(&data, None);
+            let dt = check_start.elapsed();
+            println!("Intersecting pairs: {}", result.pairs.len());
+            println!("Intersecting faces: {}", result.faces.len());
+            println!("Checked in {:?}", dt);
+        },
+        Command::Overhangs { input, direction, angle } => {
+            let data = load(input, start_time, units, up);
+            println!("{}", overhang::find_overhangs(&data, direction.unwrap_or(data.up), angle));
+        },
+        Command::Thickness { input, min } => {
+            let data = load(input, start_time, units, up);
+            let thickness = thickness::wall_thickness(&data, None);
+            println!("{}", thickness::ThicknessReport::new(&thickness, data.units.millimetres_to_units(min), data.units));
+        },
+        Command::Slice { input, axis, at, layer_height, svg, dxf } => {
+            let data = load(input, start_time, units, up);
+            let axis = axis.unwrap_or_else(|| data.up.axis());
+            let layers = match at {
+                Some(offset) => vec![slice::slice(&data, &SectionPlane::axis(axis, &data.bounds).with_offset(offset))],
+                None => slice::slice_layers(&data, axis, layer_height),
+            };
+            for layer in &layers {
+                println!("{}", layer);
+            }
+            if let Some(path) = svg {
+                if let Err(e) = writer::write_svg(&path, &layers) {
+                    eprintln!("Could not write {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            if let Some(path) = dxf {
+                if let Err(e) = writer::write_dxf(&path, &layers) {
+                    eprintln!("Could not write {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Command::Info { input, json } => {
+            let data = load(input, start_time, units, up);
+            let info = ModelInfo::new(&data, density);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info).expect("Could not serialize model info"));
+            } else {
+                println!("{}", info);
+            }
+        },
+    }
+}
+
+async fn run(start_time: SystemTime, args: Args, event_loop: EventLoop<()>, window: Window) {
     let size = window.inner_size();
     let instance = wgpu::Instance::new(wgpu::Backends::all());
     let surface = unsafe { instance.create_surface(&window)};
     
-    let loader = Loader::new(filename.unwrap(), start_time, None);
+    let filename = args.filename.expect("No file given");
+    let (units, up) = (args.units, args.up);
+    let loader_filename = filename.clone();
     let data_future = tokio::spawn(
         async move {
-            loader.run()            
+            load(loader_filename, start_time, units, up)
         }
     );
 
@@ -67,16 +296,29 @@ async fn run(start_time: SystemTime, filename: Option<String>, event_loop: Event
 
     let device_start_time = SystemTime::now();
     let dt = device_start_time.duration_since(start_time).expect("Negative start time calculated?");
-    println!("GPU startup in {:?}", dt);
+    eprintln!("GPU startup in {:?}", dt);
 
     let theme = Theme::Dark;
 
     let data = data_future.await.unwrap();
-    let model = {
-        Some(Model::new(&device, &config,  &theme, data.vertices.as_slice(), data.indices.as_slice()))
-    };
         
-    let mut state = State::new(start_time, model, theme, data.bounds, size, surface, device, config);
+    let options = Options {
+        filename,
+        theme,
+        density: args.density,
+        orbit_center: args.orbit_center,
+        sample_count: model::supported_sample_count(args.msaa, &adapter),
+        ao_radius: args.ao_radius,
+        ao_strength: args.ao_strength.clamp(0.0, 1.0),
+        environment: args.environment,
+        material: args.material,
+        matcaps: args.matcap,
+        grid: args.grid,
+        printer: args.printer,
+        perspective: args.perspective,
+        field_of_view: args.fov,
+    };
+    let mut state = State::new(start_time, data, options, size, surface, device, config);
 
     event_loop.run(move |event, _, control_flow|  {
         *control_flow = ControlFlow::Wait;
@@ -134,10 +376,34 @@ async fn main() {
         }
     }
     let args = Args::parse();
+    if let Some(command) = args.command {
+        run_headless(start, command, args.density, args.units, args.up);
+        return;
+    }
 
     let event_loop = EventLoop::new();
     let window = Window::new(&event_loop).unwrap();
     window.set_inner_size(PhysicalSize::new(1200, 800));
     window.set_title("hustl");
-    run(start, args.filename, event_loop, window).await;
+    run(start, args, event_loop, window).await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use clap::Parser;
+    use crate::units::BuildDirection;
+    use super::{load, Args, Command};
+
+    #[test]
+    fn test_up_after_subcommand() {
+        let args = Args::try_parse_from(["hustl", "slice", "assets/cube.stl", "--up", "-y"]).unwrap();
+        assert_eq!(args.up, BuildDirection::NegY);
+        let data = load("assets/cube.stl".to_string(), SystemTime::now(), None, args.up);
+        assert_eq!(data.up, BuildDirection::NegY);
+        match args.command {
+            Some(Command::Slice { axis, .. }) => assert_eq!(axis.unwrap_or_else(|| data.up.axis()), 1),
+            _ => panic!("Expected the slice command"),
+        }
+    }
 }
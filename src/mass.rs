@@ -0,0 +1,115 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, DMat3};
+use crate::loader::ModelData;
+
+/// Volume and inertial properties of a closed, outward-facing mesh. All values are in model units,
+/// with mass computed from a density given in mass per cubic model unit.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MassProperties {
+    pub volume: f64,
+    pub area: f64,
+    pub mass: f64,
+    /// Centre of mass (equal to the volume centroid for a uniform density)
+    pub centroid: [f64; 3],
+    /// Inertia tensor about the centre of mass, row-major
+    pub inertia: [[f64; 3]; 3],
+}
+
+/// Covariance of the canonical tetrahedron (0, e1, e2, e3), which every tetrahedron is mapped from.
+/// See Blow & Binstock, "How to find the inertia tensor (or other mass properties) of a 3D solid body
+/// represented by a triangle mesh".
+fn canonical_covariance() -> DMat3 {
+    DMat3::new(
+        2.0, 1.0, 1.0,
+        1.0, 2.0, 1.0,
+        1.0, 1.0, 2.0,
+    ) / 120.0
+}
+
+/// Computes mass properties by summing the signed tetrahedra formed by each triangle and the origin.
+/// The result is only meaningful for watertight meshes with consistent winding; see [`ModelData::repair`].
+pub fn mass_properties(data: &ModelData, density: f64) -> MassProperties {
+    let canonical = canonical_covariance();
+    //work relative to a point near the model to limit floating point cancellation on far-away parts
+    let origin = DVec3::new(
+        (data.bounds.x.0 + data.bounds.x.1) as f64 / 2.0,
+        (data.bounds.y.0 + data.bounds.y.1) as f64 / 2.0,
+        (data.bounds.z.0 + data.bounds.z.1) as f64 / 2.0,
+    );
+    let origin = if origin.iter().all(|v| v.is_finite()) { origin } else { DVec3::zeros() };
+
+    let mut volume = 0.0;
+    let mut area = 0.0;
+    let mut first_moment = DVec3::zeros();
+    let mut covariance = DMat3::zeros();
+
+    for tri in data.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| {
+            let p = data.vertices[tri[i] as usize].pos;
+            DVec3::new(p[0] as f64, p[1] as f64, p[2] as f64) - origin
+        });
+        area += (b - a).cross(&(c - a)).magnitude() / 2.0;
+
+        let transform = DMat3::from_columns(&[a, b, c]);
+        let det = transform.determinant();
+        volume += det / 6.0;
+        first_moment += det / 6.0 * (a + b + c) / 4.0;
+        covariance += det * transform * canonical * transform.transpose();
+    }
+
+    let centroid = if volume != 0.0 { first_moment / volume } else { DVec3::zeros() };
+    //parallel axis theorem, moving the covariance from the origin to the centroid
+    let covariance = covariance - volume * centroid * centroid.transpose();
+    let inertia = (DMat3::identity() * covariance.trace() - covariance) * density;
+    //the tensor is symmetric, but rounding in the sums above may have introduced tiny differences
+    let inertia = (inertia + inertia.transpose()) / 2.0;
+    let centroid = centroid + origin;
+
+    MassProperties {
+        volume,
+        area,
+        mass: volume * density,
+        centroid: [centroid.x, centroid.y, centroid.z],
+        inertia: [0, 1, 2].map(|r| [0, 1, 2].map(|c| inertia[(r, c)])),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use crate::loader::Loader;
+    use super::mass_properties;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-6 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    fn check_cube(filename: &str, side: f64, centroid: [f64; 3], density: f64) {
+        let data = Loader::new(filename.to_string(), SystemTime::now(), Some(1)).run();
+        let props = mass_properties(&data, density);
+        let mass = side.powi(3) * density;
+
+        assert_close(props.volume, side.powi(3));
+        assert_close(props.area, 6.0 * side * side);
+        assert_close(props.mass, mass);
+        for (c, expected) in props.centroid.iter().zip(centroid) {
+            assert_close(*c, expected);
+        }
+        for r in 0..3 {
+            for c in 0..3 {
+                let expected = if r == c { mass * side * side / 6.0 } else { 0.0 };
+                assert_close(props.inertia[r][c], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_cube() {
+        check_cube("assets/cube.stl", 20.0, [-45.0, 50.0, 10.0], 1.0);
+    }
+
+    #[test]
+    fn test_ascii_cube() {
+        check_cube("assets/cube-ascii.stl", 10.0, [5.0, 5.0, 5.0], 0.00124);
+    }
+}
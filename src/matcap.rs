@@ -0,0 +1,167 @@
+/// Width and height of the matcap texture. Other sizes are resized to it.
+const MATCAP_SIZE: u32 = 256;
+
+/// Decoded image in RGBA, row by row from the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    /// Nearest-neighbour resize, which is enough for textures that are filtered when sampled anyway
+    pub fn resize(&self, width: u32, height: u32) -> Self {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let sx = (x as u64 * self.width as u64 / width as u64) as u32;
+                let sy = (y as u64 * self.height as u64 / height as u64) as u32;
+                self.pixels[(sy * self.width + sx) as usize]
+            })
+            .collect();
+        Self { width, height, pixels }
+    }
+}
+
+/// Decodes a PNG file of any colour type and bit depth into 8-bit RGBA
+fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    //palettes and low bit depths are expanded, and 16-bit channels cut to 8 bits
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let data = &buffer[..info.buffer_size()];
+    let pixels = match info.color_type {
+        png::ColorType::Grayscale => data.iter().map(|g| [*g, *g, *g, 255]).collect(),
+        png::ColorType::GrayscaleAlpha => data.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => data.chunks_exact(3).map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::Rgba => data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+        png::ColorType::Indexed => return Err("Palette was not expanded".to_string()),
+    };
+    Ok(Image { width: info.width, height: info.height, pixels })
+}
+
+/// Lit spheres, as seen by the camera, that shade a surface by looking up its view-space normal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Matcap {
+    BuiltIn(&'static str, &'static [u8]),
+    /// Path to a PNG file
+    File(String),
+}
+
+impl Matcap {
+    pub fn built_in() -> Vec<Matcap> {
+        vec![
+            Matcap::BuiltIn("Clay", include_bytes!("../assets/matcaps/clay.png")),
+            Matcap::BuiltIn("Red wax", include_bytes!("../assets/matcaps/red_wax.png")),
+            Matcap::BuiltIn("Chrome", include_bytes!("../assets/matcaps/chrome.png")),
+            Matcap::BuiltIn("Jade", include_bytes!("../assets/matcaps/jade.png")),
+        ]
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Matcap::BuiltIn(name, _) => name,
+            Matcap::File(path) => path,
+        }
+    }
+
+    /// Decodes the image at the size of the texture
+    pub fn load(&self) -> Result<Image, String> {
+        let image = match self {
+            Matcap::BuiltIn(_, bytes) => decode_png(bytes)?,
+            Matcap::File(path) => decode_png(&std::fs::read(path).map_err(|e| e.to_string())?)?,
+        };
+        Ok(image.resize(MATCAP_SIZE, MATCAP_SIZE))
+    }
+}
+
+/// The selected matcap, sampled by the model shader
+pub struct MatcapTexture {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl MatcapTexture {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Matcap texture"),
+            size: wgpu::Extent3d {
+                width: MATCAP_SIZE,
+                height: MATCAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            //matcaps are painted in sRGB, like any other image
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    /// Replaces the texture contents with an image from [`Matcap::load`]
+    pub fn upload(&self, queue: &wgpu::Queue, image: &Image) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&image.pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(image.width * 4),
+                rows_per_image: std::num::NonZeroU32::new(image.height),
+            },
+            wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_png, Matcap, MATCAP_SIZE};
+
+    #[test]
+    fn test_decode_png() {
+        //7x5 RGB image of (30x, 40y, 5xy + x), compressed with zlib and using every filter type
+        const GRADIENT: [u8; 144] = [137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 7, 0, 0,
+            0, 5, 8, 2, 0, 0, 0, 6, 248, 97, 143, 0, 0, 0, 87, 73, 68, 65, 84, 120, 218, 93, 202, 177, 9, 128, 48, 20,
+            6, 225, 51, 49, 41, 34, 242, 68, 126, 17, 155, 212, 111, 8, 135, 176, 118, 18, 39, 113, 18, 7, 52, 182,
+            194, 85, 31, 7, 80, 233, 118, 194, 73, 188, 232, 111, 210, 67, 238, 240, 166, 249, 87, 104, 138, 39, 188,
+            224, 134, 11, 223, 240, 26, 57, 48, 13, 166, 209, 52, 153, 102, 211, 98, 90, 251, 239, 37, 65, 1, 3, 193,
+            210, 122, 1, 204, 253, 7, 35, 250, 131, 252, 204, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130];
+        let image = decode_png(&GRADIENT).unwrap();
+        assert_eq!((image.width, image.height), (7, 5));
+        for y in 0..5 {
+            for x in 0..7 {
+                let expected = [x as u8 * 30, y as u8 * 40, (x * y * 5 + x) as u8, 255];
+                assert_eq!(image.pixels[y * 7 + x], expected);
+            }
+        }
+
+        assert!(decode_png(&GRADIENT[..100]).is_err());
+        assert!(decode_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_built_in_matcaps() {
+        for matcap in Matcap::built_in() {
+            let image = matcap.load().unwrap();
+            assert_eq!(image.pixels.len(), (MATCAP_SIZE * MATCAP_SIZE) as usize);
+            //the sphere is lit from the upper left
+            let brightness = |x: usize, y: usize| image.pixels[y * 256 + x][..3].iter().map(|c| *c as u32).sum::<u32>();
+            assert!(brightness(96, 64) > brightness(160, 192), "{}", matcap.name());
+        }
+        assert!(Matcap::File("missing.png".to_string()).load().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::picking::Pick;
+use crate::units::Units;
+
+/// What clicking on the model measures
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    /// Distance and axis deltas between two points
+    Distance,
+    /// Angle between the normals of two faces
+    Angle,
+    /// Radius of the circle through three points, e.g. on a hole or fillet edge
+    Radius,
+}
+
+impl Tool {
+    pub fn picks_needed(&self) -> usize {
+        match self {
+            Tool::Distance | Tool::Angle => 2,
+            Tool::Radius => 3,
+        }
+    }
+
+    /// Next tool in the cycle, or `None` to leave measurement mode
+    pub fn next(&self) -> Option<Self> {
+        match self {
+            Tool::Distance => Some(Tool::Angle),
+            Tool::Angle => Some(Tool::Radius),
+            Tool::Radius => None,
+        }
+    }
+
+    /// Instructions shown while the tool is active
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Tool::Distance => "Measure distance: click two points",
+            Tool::Angle => "Measure angle: click two faces",
+            Tool::Radius => "Measure radius: click three points on a circular edge",
+        }
+    }
+}
+
+/// A completed measurement, kept on screen until cleared
+#[derive(Debug, Clone, PartialEq)]
+pub enum Measurement {
+    Distance { a: Vec3, b: Vec3 },
+    Angle { a: Pick, b: Pick },
+    Radius { points: [Vec3; 3], center: Vec3, radius: f32 },
+}
+
+impl Measurement {
+    /// Builds the measurement once `picks` holds as many points as the tool needs.
+    /// Returns `None` for three collinear points, which have no circle through them.
+    pub fn new(tool: Tool, picks: &[Pick]) -> Option<Self> {
+        if picks.len() < tool.picks_needed() {
+            return None;
+        }
+        match tool {
+            Tool::Distance => Some(Measurement::Distance { a: picks[0].point, b: picks[1].point }),
+            Tool::Angle => Some(Measurement::Angle { a: picks[0], b: picks[1] }),
+            Tool::Radius => {
+                let points = [picks[0].point, picks[1].point, picks[2].point];
+                circle_through(&points).map(|(center, radius)| Measurement::Radius { points, center, radius })
+            },
+        }
+    }
+
+    /// Text of the label, with lengths in the model's units
+    pub fn describe(&self, units: Units) -> String {
+        match self {
+            Measurement::Distance { a, b } => {
+                let d = b - a;
+                format!("{:.3} {} (dX {:.3}, dY {:.3}, dZ {:.3})", d.magnitude(), units, d.x, d.y, d.z)
+            },
+            Measurement::Angle { a, b } => format!("{:.2}°", angle_between(&a.normal, &b.normal)),
+            Measurement::Radius { radius, .. } => format!("R {:.3} {} (Ø {:.3})", radius, units, radius * 2.0),
+        }
+    }
+
+    /// Where the label is placed, in model coordinates
+    pub fn label_position(&self) -> Vec3 {
+        match self {
+            Measurement::Distance { a, b } => (a + b) / 2.0,
+            Measurement::Angle { a, b } => (a.point + b.point) / 2.0,
+            Measurement::Radius { center, .. } => *center,
+        }
+    }
+
+    /// Points marked on the model
+    pub fn points(&self) -> Vec<Vec3> {
+        match self {
+            Measurement::Distance { a, b } => vec![*a, *b],
+            Measurement::Angle { a, b } => vec![a.point, b.point],
+            Measurement::Radius { points, center, .. } => vec![points[0], points[1], points[2], *center],
+        }
+    }
+
+    /// Lines drawn between the marked points
+    pub fn lines(&self) -> Vec<(Vec3, Vec3)> {
+        match self {
+            Measurement::Distance { a, b } => vec![(*a, *b)],
+            Measurement::Angle { a, b } => vec![(a.point, b.point)],
+            Measurement::Radius { points, center, .. } => vec![(*center, points[0])],
+        }
+    }
+}
+
+/// Angle between two face normals in degrees: 0 for parallel faces, 90 for perpendicular ones
+pub fn angle_between(a: &Vec3, b: &Vec3) -> f32 {
+    glm::angle(a, b).to_degrees()
+}
+
+/// Centre and radius of the circle through three points, or `None` if they are (nearly) collinear
+pub fn circle_through(p: &[Vec3; 3]) -> Option<(Vec3, f32)> {
+    let a = p[0] - p[2];
+    let b = p[1] - p[2];
+    let axb = a.cross(&b);
+    let denominator = 2.0 * axb.magnitude_squared();
+    if denominator <= f32::EPSILON * a.magnitude_squared() * b.magnitude_squared() {
+        return None;
+    }
+    //circumcentre relative to the third point
+    let offset = (b * a.magnitude_squared() - a * b.magnitude_squared()).cross(&axb) / denominator;
+    Some((p[2] + offset, offset.magnitude()))
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra_glm::Vec3;
+    use crate::picking::Pick;
+    use crate::units::Units;
+    use super::{angle_between, circle_through, Measurement, Tool};
+
+    fn pick(point: Vec3, normal: Vec3) -> Pick {
+        Pick { point, normal, triangle: 0 }
+    }
+
+    #[test]
+    fn test_circle_through() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let points = [0.0f32, 1.0, 2.5].map(|t| center + Vec3::new(t.cos(), 0.0, t.sin()) * 4.0);
+        let (c, r) = circle_through(&points).unwrap();
+        assert!((c - center).magnitude() < 1e-4, "{:?}", c);
+        assert!((r - 4.0).abs() < 1e-4);
+
+        let collinear = [Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0), Vec3::new(2.0, 2.0, 2.0)];
+        assert_eq!(circle_through(&collinear), None);
+    }
+
+    #[test]
+    fn test_measurements() {
+        let up = Vec3::z();
+        let picks = [pick(Vec3::new(0.0, 0.0, 0.0), up), pick(Vec3::new(3.0, 4.0, 0.0), Vec3::x())];
+        assert_eq!(Measurement::new(Tool::Radius, &picks), None);
+
+        let distance = Measurement::new(Tool::Distance, &picks).unwrap();
+        assert_eq!(distance.describe(Units::Millimetres), "5.000 mm (dX 3.000, dY 4.000, dZ 0.000)");
+        assert_eq!(distance.label_position(), Vec3::new(1.5, 2.0, 0.0));
+
+        let angle = Measurement::new(Tool::Angle, &picks).unwrap();
+        assert_eq!(angle.describe(Units::Inches), "90.00°");
+        assert!((angle_between(&up, &-up) - 180.0).abs() < 1e-4);
+    }
+}
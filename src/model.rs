@@ -1,37 +1,149 @@
 use wgpu::util::DeviceExt;
 use nalgebra_glm as glm;
-use glm::{Mat4};
-use crate::loader::Vertex;
+use glm::Mat4;
+use std::ops::Range;
+use crate::loader::{ModelData, Vertex};
 use crate::camera::Camera;
 use crate::color::Theme;
+use crate::edges::FeatureEdges;
+use crate::settings::{RenderMode, RenderSettings, SettingsUniform};
+use crate::environment::EnvironmentMap;
+use crate::matcap::MatcapTexture;
+use crate::shadow::{ShadowMap, LIGHT_ENTRY, SHADOW_FORMAT};
+use crate::xray::{XRay, ACCUMULATION_FORMAT, REVEALAGE_FORMAT};
+
+/// Format of the depth buffer shared by every pass. The stencil is used to cap cross-sections.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// Shader source for a screen-space pass that reads the depth texture as `texture_depth_2d`. With MSAA the
+/// texture is multisampled; textureLoad then takes a sample index instead of a mip level, so only the type differs.
+pub fn depth_reading_shader(source: &'static str, sample_count: u32) -> std::borrow::Cow<'static, str> {
+    if sample_count > 1 {
+        source.replace("texture_depth_2d", "texture_depth_multisampled_2d").into()
+    } else {
+        source.into()
+    }
+}
+
+/// View of the depth texture for binding to a screen-space pass, which can't see the stencil
+pub fn depth_reading_view(depth: &wgpu::Texture) -> wgpu::TextureView {
+    depth.create_view(&wgpu::TextureViewDescriptor {
+        aspect: wgpu::TextureAspect::DepthOnly,
+        ..Default::default()
+    })
+}
+
+/// Attachments shared by the passes that draw the scene
+pub struct RenderTarget<'a> {
+    /// Multisampled with MSAA, otherwise the frame itself
+    pub color: &'a wgpu::TextureView,
+    /// The frame, if `color` is multisampled
+    pub resolve: Option<&'a wgpu::TextureView>,
+    pub depth: &'a wgpu::TextureView,
+}
+
+/// Textures the model is shaded with, which are kept when the model is rebuilt
+pub struct ShadingTextures<'a> {
+    pub environment: &'a EnvironmentMap,
+    pub matcap: &'a MatcapTexture,
+}
+
+/// Samples per pixel for anti-aliasing, unless asked for otherwise. Every adapter supports 4.
+pub const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
+/// Parses an MSAA sample count for the command line: 1 (off), 2, 4 or 8
+pub fn parse_sample_count(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err(format!("Unsupported sample count '{}', expected 1, 2, 4 or 8", s)),
+    }
+}
+
+/// The requested sample count, or 4 where the adapter may not support more. wgpu can't be asked which
+/// counts a format supports, but OpenGL ES and WebGL only guarantee 4.
+pub fn supported_sample_count(requested: u32, adapter: &wgpu::Adapter) -> u32 {
+    if requested != 1 && requested != 4 && adapter.get_info().backend == wgpu::Backend::Gl {
+        eprintln!("{}x MSAA may not be supported by OpenGL, using 4x", requested);
+        return 4;
+    }
+    requested
+}
+
+/// Per-vertex results of mesh analysis. These live in a second vertex buffer so that
+/// [`Vertex`] stays exactly what the loader produces.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VertexAttributes {
+    pub shell: u32,
+    /// Wall thickness, or [`NO_THICKNESS`](crate::thickness::NO_THICKNESS) if unknown
+    pub thickness: f32,
+    /// Mean and Gaussian curvature, see [`Curvature`](crate::curvature::Curvature)
+    pub curvature: [f32; 2],
+}
+
 pub struct Model {
     vertex_buffer: wgpu::Buffer,
+    attribute_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
     color_bind_group: wgpu::BindGroup,
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
-    num_indices: u32,
+    highlight_pipeline: wgpu::RenderPipeline,
+    section_stencil_pipeline: wgpu::RenderPipeline,
+    section_cap_pipeline: wgpu::RenderPipeline,
+    hidden_line_pipeline: wgpu::RenderPipeline,
+    wire_pipeline: wgpu::RenderPipeline,
+    wire_overlay_pipeline: wgpu::RenderPipeline,
+    /// Unindexed copy of the triangles for drawing edges, created the first time they are shown
+    wire_buffer: Option<wgpu::Buffer>,
+    edge_pipeline: wgpu::RenderPipeline,
+    /// Line index buffer of the sharp edges, and which triangles they belong to
+    feature_edges: Option<(wgpu::Buffer, FeatureEdges)>,
+    /// Index buffer and index count of the faces drawn with the highlight colour
+    highlight: Option<(wgpu::Buffer, u32)>,
+    /// Ranges of the index buffer to draw, used to hide shells
+    draw_ranges: Vec<Range<u32>>,
+    shadow: ShadowMap,
+    /// Shadow map, key light, environment and matcap, sampled while shading
+    lighting_bind_group: wgpu::BindGroup,
+    shadow_pipeline: wgpu::RenderPipeline,
+    ground_shadow_pipeline: wgpu::RenderPipeline,
+    xray_pipeline: wgpu::RenderPipeline,
+    xray_depth_pipeline: wgpu::RenderPipeline,
+    wire_hidden_pipeline: wgpu::RenderPipeline,
+    edge_hidden_pipeline: wgpu::RenderPipeline,
 }
 
 impl Model {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme, vertices: &[Vertex], indices: &[u32]) -> Self {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme, sample_count: u32,
+        data: &ModelData, attributes: &[VertexAttributes], textures: &ShadingTextures) -> Self {
+        let (vertices, indices) = (data.vertices.as_slice(), data.indices.as_slice());
         
-        println!("Polygons: {}", indices.len()/3);
+        eprintln!("Polygons: {}", indices.len()/3);
 
         // println!("{:?}", vertices);
 
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor{
                 label: Some("Model vertex buffer"),
-                contents: bytemuck::cast_slice(&vertices),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let attribute_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor{
+                label: Some("Model vertex attribute buffer"),
+                contents: bytemuck::cast_slice(attributes),
                 usage: wgpu::BufferUsages::VERTEX,
             }
         );
         let index_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Model index buffer"),
-                contents: bytemuck::cast_slice(&indices),
+                contents: bytemuck::cast_slice(indices),
                 usage: wgpu::BufferUsages::INDEX,
             }
         );
@@ -117,12 +229,167 @@ impl Model {
             }
         );
 
+        let settings_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Render settings buffer"),
+                size: std::mem::size_of::<SettingsUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+
+        let settings_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer { 
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false, 
+                            min_binding_size: None, 
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("Render settings bind group layout"),
+            }
+        );
+
+        let settings_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &settings_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: settings_buffer.as_entire_binding(),
+                    }
+                ],
+                label: Some("Render settings bind group"),
+            }
+        );
+
+        let shadow = ShadowMap::new(device, &data.bounds, &data.up.up_rotation());
+
+        let lighting_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                        count: None
+                    },
+                    LIGHT_ENTRY,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("Lighting bind group layout"),
+            }
+        );
+
+        let lighting_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &lighting_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&shadow.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&shadow.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: shadow.light_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&textures.environment.radiance_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&textures.environment.irradiance_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::Sampler(&textures.environment.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::TextureView(&textures.matcap.view),
+                    },
+                ],
+                label: Some("Lighting bind group"),
+            }
+        );
+
         let pipeline_layout = device.create_pipeline_layout(
             &wgpu::PipelineLayoutDescriptor {
                 label: Some("Model render pipeline layout"),
                 bind_group_layouts: &[
                     &camera_bind_group_layout,
                     &color_bind_group_layout,
+                    &settings_bind_group_layout,
+                    &lighting_bind_group_layout,
+                ],
+                push_constant_ranges: &[]
+            }
+        );
+
+        let shadow_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model shadow pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &color_bind_group_layout,
+                    &settings_bind_group_layout,
+                    &shadow.pass_bind_group_layout,
                 ],
                 push_constant_ranges: &[]
             }
@@ -141,6 +408,31 @@ impl Model {
             ]
         };
 
+        let attribute_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<VertexAttributes>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                //shell index
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: 0,
+                    shader_location: 1,
+                },
+                //wall thickness
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                //mean and Gaussian curvature
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: (std::mem::size_of::<u32>() + std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+            ]
+        };
+
         let shader = device.create_shader_module(
             &wgpu::ShaderModuleDescriptor {
                 label: Some("Module shader"),
@@ -148,6 +440,8 @@ impl Model {
             }
         );
 
+        let buffers = [vertex_buf_layout, attribute_buf_layout];
+
         let render_pipeline = device.create_render_pipeline(
             &wgpu::RenderPipelineDescriptor {
                 label: Some("Model render pipeline"),
@@ -155,7 +449,7 @@ impl Model {
                 vertex: wgpu::VertexState {
                     module: &shader,
                     entry_point: "vs_main",
-                    buffers: &[vertex_buf_layout],
+                    buffers: &buffers,
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &shader,
@@ -171,19 +465,19 @@ impl Model {
                     strip_index_format: None,
                     front_face: wgpu::FrontFace::Ccw,
                     cull_mode: None,
-                    polygon_mode: wgpu::PolygonMode::Fill, //this may change depending on the settings
+                    polygon_mode: wgpu::PolygonMode::Fill, //edges are drawn by the wireframe pipelines instead
                     unclipped_depth: false,
                     conservative: false,
                 },
                 depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
+                    format: DEPTH_FORMAT,
                     depth_write_enabled: true,
                     depth_compare: wgpu::CompareFunction::Greater,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
                 multisample: wgpu::MultisampleState {
-                    count: 1,
+                    count: sample_count,
                     mask: !0,
                     alpha_to_coverage_enabled: false,
                 },
@@ -191,74 +485,966 @@ impl Model {
             }
         );
 
-        Self {
-            render_pipeline,
-            index_buffer,
-            vertex_buffer,
-            camera_buffer,
-            camera_bind_group,
-            color_bind_group,
-            num_indices: indices.len() as u32
-        }
-
-    }
-
-    pub fn draw(&self, 
-        camera: &Camera, 
-        frame: &wgpu::SurfaceTexture, 
-        depth_view: &wgpu::TextureView, 
-        encoder: &mut wgpu::CommandEncoder, 
-        queue: &wgpu::Queue) {
-        let view_matrix = camera.view_matrix();
-        let model_matrix = camera.model_matrix();
-        let proj_matrix = camera.proj_matrix();
-
-        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(view_matrix.as_slice()));
-        queue.write_buffer(&self.camera_buffer, 
-            std::mem::size_of::<Mat4>() as wgpu::BufferAddress, 
-            bytemuck::cast_slice(model_matrix.as_slice())
-        );
-        queue.write_buffer(&self.camera_buffer, 
-            std::mem::size_of::<Mat4>() as wgpu::BufferAddress*2, 
-            bytemuck::cast_slice(proj_matrix.as_slice())
+        //draws flagged faces over the model. Depth is tested but not written, so the faces must already be in the depth buffer.
+        let highlight_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model highlight pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_highlight",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
         );
 
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        
-        let mut render_pass = encoder.begin_render_pass(
-            &wgpu::RenderPassDescriptor {
-                label: Some("Model render pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    }
-                }],
-                depth_stencil_attachment: Some (
-                    wgpu::RenderPassDepthStencilAttachment {
-                        view: &depth_view,
-                        depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: true
-                        }),
-                        stencil_ops: None,
-                    }
-                )
+        //counts the surfaces behind each pixel that are not cut away, see fs_section_stencil
+        let section_stencil_face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Invert,
+        };
+        let section_stencil_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model section stencil pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_section_stencil",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState {
+                        front: section_stencil_face,
+                        back: section_stencil_face,
+                        read_mask: 0xff,
+                        write_mask: 0xff,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
             }
         );
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.color_bind_group, &[]);
-        //TODO: replace with indexed draw call
-        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        //fills the section where the stencil count is odd. Only the lowest bit is read.
+        let section_cap_face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::NotEqual,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+        let section_cap_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model section cap pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_section_cap",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_section_cap",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: wgpu::StencilState {
+                        front: section_cap_face,
+                        back: section_cap_face,
+                        read_mask: 0x01,
+                        write_mask: 0x00,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //flat fill for hidden-line drawing, otherwise the same as the render pipeline
+        let hidden_line_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model hidden line pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_hidden_line",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //X-ray layers, blended in any order: colour and alpha are summed, revealage is multiplied by 1 - alpha.
+        //Depth is tested against the background but not written, so every layer is drawn.
+        let xray_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model X-ray pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_xray",
+                    targets: &[
+                        wgpu::ColorTargetState {
+                            format: ACCUMULATION_FORMAT,
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::One,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::One,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        },
+                        wgpu::ColorTargetState {
+                            format: REVEALAGE_FORMAT,
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::Zero,
+                                    dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::Zero,
+                                    dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        },
+                    ]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //depth of the front surface after the X-ray layers are composited, which peels the edges
+        //into those in front and those behind, and lets the silhouette and ambient occlusion find the model
+        let xray_depth_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model X-ray depth pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_hidden_line",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        let wire_buf_layout = [wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                //positions
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+            ]
+        }];
+
+        //every edge, without depth testing
+        let wire_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model wireframe pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_wire",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_wire",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //edges of the surface already in the depth buffer. The slope bias pulls them towards the camera
+        //(depth is reversed), so they don't fight with the surface at grazing angles.
+        let wire_overlay_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model wireframe overlay pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_wire",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_wire_overlay",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState {
+                        constant: 0,
+                        slope_scale: 1.0,
+                        clamp: 0.0,
+                    },
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //X-ray edges behind the front surface, the opposite depth test of the overlay
+        let wire_hidden_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model wireframe hidden pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_wire",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_wire_hidden",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState {
+                        constant: 0,
+                        slope_scale: 1.0,
+                        clamp: 0.0,
+                    },
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //feature edges as lines over the model vertex buffer, see vs_edge for how they stay in front of the surface
+        let edge_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model feature edge pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_edge",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_edge",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //X-ray feature edges behind the front surface
+        let edge_hidden_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model feature edge hidden pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_edge",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_edge_hidden",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        //depth only, as seen from the key light. The slope bias keeps surfaces from shadowing themselves.
+        let shadow_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model shadow pipeline"),
+                layout: Some(&shadow_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_shadow",
+                    buffers: &wire_buf_layout,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_shadow",
+                    targets: &[]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: SHADOW_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState {
+                        constant: 2,
+                        slope_scale: 2.0,
+                        clamp: 0.0,
+                    },
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        //darkens the ground below the model where it is in shadow, behind the model
+        let ground_shadow_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model ground shadow pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_ground",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_ground",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        Self {
+            render_pipeline,
+            highlight_pipeline,
+            section_stencil_pipeline,
+            section_cap_pipeline,
+            hidden_line_pipeline,
+            wire_pipeline,
+            wire_overlay_pipeline,
+            wire_buffer: None,
+            edge_pipeline,
+            feature_edges: None,
+            highlight: None,
+            index_buffer,
+            vertex_buffer,
+            attribute_buffer,
+            camera_buffer,
+            camera_bind_group,
+            color_bind_group,
+            settings_buffer,
+            settings_bind_group,
+            draw_ranges: std::iter::once(0..indices.len() as u32).collect(),
+            shadow,
+            lighting_bind_group,
+            shadow_pipeline,
+            ground_shadow_pipeline,
+            xray_pipeline,
+            xray_depth_pipeline,
+            wire_hidden_pipeline,
+            edge_hidden_pipeline,
+        }
+
+    }
+
+    /// Restrict drawing to the given ranges of the index buffer
+    pub fn set_draw_ranges(&mut self, ranges: Vec<Range<u32>>) {
+        self.draw_ranges = ranges;
+    }
+
+    /// Replace the per-vertex attributes, e.g. once an analysis has finished
+    pub fn set_attributes(&mut self, device: &wgpu::Device, attributes: &[VertexAttributes]) {
+        self.attribute_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor{
+                label: Some("Model vertex attribute buffer"),
+                contents: bytemuck::cast_slice(attributes),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+    }
+
+    /// Creates the buffer needed for drawing edges, unless it already exists. Vertex `i` of the
+    /// buffer is `vertices[indices[i]]`, so the draw ranges apply to it unchanged.
+    pub fn prepare_edges(&mut self, device: &wgpu::Device, vertices: &[Vertex], indices: &[u32]) {
+        if self.wire_buffer.is_some() {
+            return;
+        }
+        let corners: Vec<Vertex> = indices.iter().map(|i| vertices[*i as usize]).collect();
+        self.wire_buffer = Some(device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor{
+                label: Some("Model wireframe vertex buffer"),
+                contents: bytemuck::cast_slice(&corners),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        ));
+    }
+
+    pub fn has_feature_edges(&self) -> bool {
+        self.feature_edges.is_some()
+    }
+
+    /// Replace the sharp edges drawn when `settings.feature_edges` is on
+    pub fn set_feature_edges(&mut self, device: &wgpu::Device, edges: FeatureEdges) {
+        if edges.is_empty() {
+            self.feature_edges = None;
+            return;
+        }
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Model feature edge index buffer"),
+                contents: bytemuck::cast_slice(&edges.lines),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        self.feature_edges = Some((buffer, edges));
+    }
+
+    /// Draw the given triangles (indices into the triangle list) in the highlight colour, or stop if empty
+    pub fn set_highlight(&mut self, device: &wgpu::Device, triangles: &[u32], indices: &[u32]) {
+        if triangles.is_empty() {
+            self.highlight = None;
+            return;
+        }
+        let highlight_indices: Vec<u32> = triangles.iter()
+            .flat_map(|t| indices[*t as usize * 3..*t as usize * 3 + 3].iter().copied())
+            .collect();
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Model highlight index buffer"),
+                contents: bytemuck::cast_slice(&highlight_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        self.highlight = Some((buffer, highlight_indices.len() as u32));
+    }
+
+    pub fn draw(&self, 
+        camera: &Camera, 
+        settings: &RenderSettings,
+        target: &RenderTarget, 
+        xray: &XRay,
+        encoder: &mut wgpu::CommandEncoder, 
+        queue: &wgpu::Queue) {
+        let view_matrix = camera.view_matrix();
+        let model_matrix = camera.model_matrix();
+        let proj_matrix = camera.proj_matrix();
+
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(view_matrix.as_slice()));
+        queue.write_buffer(&self.camera_buffer, 
+            std::mem::size_of::<Mat4>() as wgpu::BufferAddress, 
+            bytemuck::cast_slice(model_matrix.as_slice())
+        );
+        queue.write_buffer(&self.camera_buffer, 
+            std::mem::size_of::<Mat4>() as wgpu::BufferAddress*2, 
+            bytemuck::cast_slice(proj_matrix.as_slice())
+        );
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(&settings.uniform()));
+        self.shadow.write(queue, settings.shadows);
+
+        if settings.shadows || settings.ground_shadow {
+            self.draw_shadow_map(encoder);
+        }
+        //X-ray replaces the opaque fill, and is blended over the scene before anything else is drawn on top
+        let xray_fill = settings.xray && settings.mode.has_fill();
+        if xray_fill {
+            self.draw_xray(xray, target, encoder);
+        }
+
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Model render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target.color,
+                    resolve_target: target.resolve,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: Some (
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: target.depth,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true
+                        }),
+                    }
+                )
+            }
+        );
+
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.attribute_buffer.slice(..));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.color_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.lighting_bind_group, &[]);
+
+        if xray_fill {
+            render_pass.set_pipeline(&self.xray_depth_pipeline);
+            for range in &self.draw_ranges {
+                render_pass.draw_indexed(range.clone(), 0, 0..1);
+            }
+        } else if settings.mode.has_fill() {
+            if settings.mode == RenderMode::HiddenLine {
+                render_pass.set_pipeline(&self.hidden_line_pipeline);
+            } else {
+                render_pass.set_pipeline(&self.render_pipeline);
+            }
+            for range in &self.draw_ranges {
+                render_pass.draw_indexed(range.clone(), 0, 0..1);
+            }
+
+            if let Some((buffer, count)) = &self.highlight {
+                render_pass.set_pipeline(&self.highlight_pipeline);
+                render_pass.set_index_buffer(buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..*count, 0, 0..1);
+            }
+
+            if settings.section.is_some() {
+                render_pass.set_pipeline(&self.section_stencil_pipeline);
+                render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                for range in &self.draw_ranges {
+                    render_pass.draw_indexed(range.clone(), 0, 0..1);
+                }
+                render_pass.set_pipeline(&self.section_cap_pipeline);
+                render_pass.set_stencil_reference(0);
+                render_pass.draw(0..6, 0..1);
+            }
+        }
+
+        //the ground is only seen from above, where it doesn't cover the model
+        if settings.ground_shadow && glm::quat_rotate_vec3(&camera.up_rotation(), &camera.towards_viewer()).z > 0.0 {
+            render_pass.set_pipeline(&self.ground_shadow_pipeline);
+            render_pass.draw(0..6, 0..1);
+        }
+
+        if let (true, Some(wire_buffer)) = (settings.mode.has_edges(), &self.wire_buffer) {
+            render_pass.set_vertex_buffer(0, wire_buffer.slice(..));
+            if xray_fill {
+                render_pass.set_pipeline(&self.wire_hidden_pipeline);
+                for range in &self.draw_ranges {
+                    render_pass.draw(range.clone(), 0..1);
+                }
+            }
+            if settings.mode.has_fill() {
+                render_pass.set_pipeline(&self.wire_overlay_pipeline);
+            } else {
+                render_pass.set_pipeline(&self.wire_pipeline);
+            }
+            for range in &self.draw_ranges {
+                render_pass.draw(range.clone(), 0..1);
+            }
+        }
+
+        if let (true, Some((buffer, edges))) = (settings.feature_edges, &self.feature_edges) {
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(buffer.slice(..), wgpu::IndexFormat::Uint32);
+            //in X-ray, the edges behind the front surface are drawn first and fainter
+            let pipelines = [xray_fill.then_some(&self.edge_hidden_pipeline), Some(&self.edge_pipeline)];
+            for pipeline in pipelines.into_iter().flatten() {
+                render_pass.set_pipeline(pipeline);
+                for range in &self.draw_ranges {
+                    let lines = edges.range(range.start / 3..range.end / 3);
+                    render_pass.draw_indexed(lines.start as u32 * 2..lines.end as u32 * 2, 0, 0..1);
+                }
+            }
+        }
+    }
+
+    /// Accumulates every visible layer of the model into the X-ray targets and blends the result into the scene
+    fn draw_xray(&self, xray: &XRay, target: &RenderTarget, encoder: &mut wgpu::CommandEncoder) {
+        {
+            let mut render_pass = xray.begin_accumulation(encoder, target.depth);
+            render_pass.set_pipeline(&self.xray_pipeline);
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.attribute_buffer.slice(..));
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.color_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.lighting_bind_group, &[]);
+            for range in &self.draw_ranges {
+                render_pass.draw_indexed(range.clone(), 0, 0..1);
+            }
+        }
+        xray.composite(target.color, encoder);
+    }
+
+    /// Renders the depth of the visible triangles, as seen from the key light, into the shadow map
+    fn draw_shadow_map(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Model shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some (
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.shadow.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true
+                        }),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        );
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.color_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.shadow.pass_bind_group, &[]);
+        for range in &self.draw_ranges {
+            render_pass.draw_indexed(range.clone(), 0, 0..1);
+        }
+    }
+
+    /// Multisampled colour target that is resolved into the surface, or `None` without MSAA
+    pub fn get_msaa_texture(config: &wgpu::SurfaceConfiguration, device: &wgpu::Device, sample_count: u32)
+        -> Option<(wgpu::Texture, wgpu::TextureView)> {
+        if sample_count == 1 {
+            return None;
+        }
+        let desc = wgpu::TextureDescriptor {
+            label: Some("Multisampled color texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        };
+        let tex = device.create_texture(&desc);
+        let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+        Some((tex, view))
     }
 
-    pub fn get_depth_texture(config: &wgpu::SurfaceConfiguration, device: &wgpu::Device) 
+    pub fn get_depth_texture(config: &wgpu::SurfaceConfiguration, device: &wgpu::Device, sample_count: u32) 
         -> (wgpu::Texture, wgpu::TextureView, wgpu::Sampler) {
         let size = wgpu::Extent3d {
             width: config.width,
@@ -270,9 +1456,9 @@ impl Model {
             label: Some("Model depth texture"),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
         };
         let tex = device.create_texture(&desc);
@@ -12,21 +12,136 @@ var<uniform> camera: CameraUniform;
 
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
+    [[location(1)]] shell: u32;
+    [[location(2)]] thickness: f32;
+    [[location(3)]] curvature: vec2<f32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
-    [[location(0)]] real_position: vec3<f32>;
+    [[location(0)]] view_position: vec3<f32>;
+    [[location(1), interpolate(flat)]] shell: u32;
+    [[location(2)]] model_position: vec3<f32>;
+    [[location(3)]] thickness: f32;
+    [[location(4)]] curvature: vec2<f32>;
 };
 
+//mirrors SettingsUniform in settings.rs
+struct Settings {
+    shading: u32;
+    selected_shell: u32;
+    overhang_threshold: f32;
+    build_plate: f32;
+    build_direction: vec3<f32>;
+    min_thickness: f32;
+    //normal in xyz and offset in w, everything with dot(normal, p) > offset is cut away
+    section_plane: vec4<f32>;
+    section_origin: vec3<f32>;
+    section_size: f32;
+    //base colour in xyz and metallic in w, used by physically based shading
+    material: vec4<f32>;
+    roughness: f32;
+    //with theme shading: 0 for the theme colours, 1 for the material, 2 for the matcap
+    surface: u32;
+    //curvature mapped to the ends of the colour map
+    curvature_range: f32;
+    //faces this close to the build plate rest on it, the same tolerance as find_overhangs
+    plate_tolerance: f32;
+};
+
+[[group(2), binding(0)]]
+var<uniform> settings: Settings;
+
+//a zero normal disables the section, since dot(normal, p) is then never above the zero offset
+fn is_cut_away(position: vec3<f32>) -> bool {
+    return dot(settings.section_plane.xyz, position) > settings.section_plane.w;
+}
+
+//shared by every vertex stage, so that passes drawing the same surface produce the same depth
+fn to_clip(position: vec3<f32>) -> vec4<f32> {
+    return camera.projection * camera.view * camera.model * vec4<f32>(position, 1.0);
+}
+
 [[stage(vertex)]]
 fn vs_main(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.position = camera.projection * camera.view * camera.model * vec4<f32>(model.position, 1.0);
-    out.real_position = out.position.xyz;
+    out.position = to_clip(model.position);
+    out.view_position = (camera.model * vec4<f32>(model.position, 1.0)).xyz;
+    out.shell = model.shell;
+    out.model_position = model.position;
+    out.thickness = model.thickness;
+    out.curvature = model.curvature;
     return out;
 }
 
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> vec3<f32> {
+    let k = vec3<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0);
+    let p = abs(fract(vec3<f32>(h, h, h) + k) * 6.0 - vec3<f32>(3.0, 3.0, 3.0));
+    return v * mix(vec3<f32>(1.0, 1.0, 1.0), clamp(p - vec3<f32>(1.0, 1.0, 1.0), vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0)), s);
+}
+
+//stepping the hue by the golden ratio keeps neighbouring shell indices visually distinct
+fn shell_color(shell: u32) -> vec3<f32> {
+    let h = fract(f32(shell) * 0.61803398875);
+    let c = hsv_to_rgb(h, 0.55, 0.95);
+    return pow(c, vec3<f32>(2.2, 2.2, 2.2));
+}
+
+//green for faces pointing up, through yellow at the overhang threshold, to red for faces pointing straight down.
+//Faces lying on the build plate are shown in blue since they need no support.
+fn overhang_color(position: vec3<f32>) -> vec3<f32> {
+    //the derivatives give a normal in model space, oriented towards the camera like every visible face
+    let normal = normalize(cross(dpdy(position), dpdx(position)));
+    let up = settings.build_direction;
+    //angle below horizontal, in degrees
+    let angle = asin(clamp(-dot(normal, up), -1.0, 1.0)) * 57.29578;
+    let threshold = settings.overhang_threshold;
+
+    var c: vec3<f32>;
+    if (dot(position, up) - settings.build_plate <= settings.plate_tolerance) {
+        c = vec3<f32>(0.35, 0.55, 0.95);
+    } else if (angle > threshold) {
+        let t = clamp((angle - threshold) / max(90.0 - threshold, 1.0), 0.0, 1.0);
+        c = mix(vec3<f32>(1.0, 0.45, 0.1), vec3<f32>(0.85, 0.0, 0.0), t);
+    } else {
+        let t = clamp((angle + 90.0) / max(threshold + 90.0, 1.0), 0.0, 1.0);
+        c = mix(vec3<f32>(0.3, 0.8, 0.35), vec3<f32>(0.95, 0.9, 0.3), t);
+    }
+    return pow(c, vec3<f32>(2.2, 2.2, 2.2));
+}
+
+//red below the minimum thickness, then through orange and yellow to green at four times the minimum.
+//Vertices without a measurement (negative thickness) are grey.
+fn thickness_color(thickness: f32) -> vec3<f32> {
+    var c: vec3<f32>;
+    if (thickness < 0.0) {
+        c = vec3<f32>(0.6, 0.6, 0.65);
+    } else if (thickness < settings.min_thickness) {
+        c = vec3<f32>(0.85, 0.05, 0.05);
+    } else {
+        let t = clamp((thickness / settings.min_thickness - 1.0) / 3.0, 0.0, 1.0);
+        if (t < 0.5) {
+            c = mix(vec3<f32>(1.0, 0.45, 0.1), vec3<f32>(0.95, 0.9, 0.3), t * 2.0);
+        } else {
+            c = mix(vec3<f32>(0.95, 0.9, 0.3), vec3<f32>(0.3, 0.8, 0.35), t * 2.0 - 1.0);
+        }
+    }
+    return pow(c, vec3<f32>(2.2, 2.2, 2.2));
+}
+
+//diverging blue through grey to red, for concave, flat and convex curvature up to the clamp range
+fn curvature_color(curvature: f32) -> vec3<f32> {
+    let t = clamp(curvature / settings.curvature_range, -1.0, 1.0);
+    let neutral = vec3<f32>(0.87, 0.87, 0.87);
+    var c: vec3<f32>;
+    if (t < 0.0) {
+        c = mix(neutral, vec3<f32>(0.23, 0.3, 0.75), -t);
+    } else {
+        c = mix(neutral, vec3<f32>(0.71, 0.02, 0.15), t);
+    }
+    return pow(c, vec3<f32>(2.2, 2.2, 2.2));
+}
+
 struct ModelColors {
     key: vec4<f32>;
     fill: vec4<f32>;
@@ -36,24 +151,382 @@ struct ModelColors {
 [[group(1), binding(0)]]
 var<uniform> colors: ModelColors;
 
-[[stage(fragment)]]
-fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    //solarized theme, factor out into separate bind group later
-    let key = colors.key.xyz;
-    let fill = colors.fill.xyz;
+//mirrors LightUniform in shadow.rs
+struct Light {
+    //model to shadow map space, with depth in z
+    matrix: mat4x4<f32>;
+    //towards the key light, in model space
+    direction: vec3<f32>;
+    shadows: f32;
+    //centre in xyz and half the size in w
+    ground: vec4<f32>;
+    //the world's X and Y axes in model space, since the ground and the sky are level in the world
+    world_x: vec4<f32>;
+    world_y: vec4<f32>;
+};
+
+[[group(3), binding(0)]]
+var shadow_map: texture_depth_2d;
+[[group(3), binding(1)]]
+var shadow_sampler: sampler_comparison;
+[[group(3), binding(2)]]
+var<uniform> light: Light;
+
+[[group(3), binding(3)]]
+var environment: texture_2d<f32>;
+[[group(3), binding(4)]]
+var irradiance: texture_2d<f32>;
+[[group(3), binding(5)]]
+var environment_sampler: sampler;
+
+//texture coordinates of a direction in model space in an equirectangular map with the world's +Z at the top
+fn equirect_uv(direction: vec3<f32>) -> vec2<f32> {
+    let x = light.world_x.xyz;
+    let y = light.world_y.xyz;
+    let d = vec3<f32>(dot(direction, x), dot(direction, y), dot(direction, cross(x, y)));
+    return vec2<f32>(atan2(d.y, d.x) * 0.15915494 + 0.5, acos(clamp(d.z, -1.0, 1.0)) * 0.31830989);
+}
+
+//Karis' analytic fit of the split-sum environment BRDF, in place of a lookup table
+fn environment_brdf(f0: vec3<f32>, roughness: f32, n_dot_v: f32) -> vec3<f32> {
+    let r = roughness * vec4<f32>(-1.0, -0.0275, -0.572, 0.022) + vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let ab = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * ab.x + vec3<f32>(ab.y, ab.y, ab.y);
+}
+
+//Narkowicz' fit of the ACES filmic curve, so that bright reflections roll off instead of clipping
+fn tone_map(c: vec3<f32>) -> vec3<f32> {
+    let mapped = (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+}
+
+//image-based lighting of a metallic/roughness material. Rougher surfaces reflect blurrier mips.
+fn pbr_color(n: vec3<f32>, v: vec3<f32>) -> vec3<f32> {
+    let base = settings.material.xyz;
+    let metallic = settings.material.w;
+    let f0 = mix(vec3<f32>(0.04, 0.04, 0.04), base, metallic);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let max_lod = f32(textureNumLevels(environment) - 1);
+    let reflected = textureSampleLevel(environment, environment_sampler, equirect_uv(reflect(-v, n)), settings.roughness * max_lod).xyz;
+    let diffuse = textureSampleLevel(irradiance, environment_sampler, equirect_uv(n), 0.0).xyz;
+    let color = diffuse * base * (1.0 - metallic) + reflected * environment_brdf(f0, settings.roughness, n_dot_v);
+    return tone_map(color);
+}
+
+[[group(3), binding(6)]]
+var matcap: texture_2d<f32>;
+
+//the matcap sphere seen head on, looked up by the normal in view space
+fn matcap_color(n: vec3<f32>) -> vec3<f32> {
+    let m = camera.view * camera.model;
+    let n_view = normalize(mat3x3<f32>(m[0].xyz, m[1].xyz, m[2].xyz) * n);
+    let uv = vec2<f32>(n_view.x, -n_view.y) * 0.49 + vec2<f32>(0.5, 0.5);
+    return textureSampleLevel(matcap, environment_sampler, uv, 0.0).xyz;
+}
+
+//fraction of the key light reaching a point, averaged over 3x3 texels of the shadow map.
+//Points outside the map are lit.
+fn shadow_visibility(position: vec3<f32>) -> f32 {
+    let p = (light.matrix * vec4<f32>(position, 1.0)).xyz;
+    let uv = vec2<f32>(p.x + 1.0, 1.0 - p.y) * 0.5;
+    if (any(uv < vec2<f32>(0.0, 0.0)) || any(uv > vec2<f32>(1.0, 1.0)) || p.z > 1.0) {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, p.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+//the view direction is the camera's z axis, brought back through the rotation of the view and model
+fn view_direction() -> vec3<f32> {
+    let m = camera.view * camera.model;
+    return normalize(transpose(mat3x3<f32>(m[0].xyz, m[1].xyz, m[2].xyz)) * vec3<f32>(0.0, 0.0, 1.0));
+}
+
+//lit colour of the surface, shared by the opaque and X-ray passes. Uses derivatives, so it must be
+//called before any discard.
+fn surface_color(in: VertexOutput) -> vec3<f32> {
+    var key = colors.key.xyz;
+    var fill = colors.fill.xyz;
     let base = colors.base.xyz;
+    if (settings.shading == 1u) {
+        key = shell_color(in.shell);
+        fill = key;
+    } else if (settings.shading == 2u) {
+        key = overhang_color(in.model_position);
+        fill = key;
+    } else if (settings.shading == 3u) {
+        key = thickness_color(in.thickness);
+        fill = key;
+    } else if (settings.shading == 4u) {
+        key = curvature_color(in.curvature.x);
+        fill = key;
+    } else if (settings.shading == 5u) {
+        key = curvature_color(in.curvature.y);
+        fill = key;
+    }
     //The shading of the fragment should depend on the relative angle from the z-normal vector. 
     // This simulates a "light" emanating from the camera and from the upper right.
     
     //we obtain the normal vector by taking the cross product of the partial derivative of position w.r.t. x and y
     //the final color is a function of the angle between the normal vector and reference vectors. 
-    var n_screen: vec3<f32> = cross( dpdx(in.real_position), dpdy(in.real_position));
-    n_screen.z = n_screen.z * camera.projection[2][2]; //this component of the projection matrix is the zoom level
+    var n_screen: vec3<f32> = cross( dpdx(in.view_position), dpdy(in.view_position));
+    n_screen.z = n_screen.z * 10.0; //exaggerated, so that surfaces turned slightly away already darken
     let normal = normalize(n_screen);
 
     //determine projection of fragment normal vector onto z unit vector
     let a = dot(normal, vec3<f32>(0.0, 0.0, -1.0));
     let b = dot(normal, vec3<f32>(-0.57, -0.57, 0.0));
 
-    return vec4<f32>(mix(base, key, a)* 0.3 + mix(base, fill, b) * 0.7, 1.0);
-}
\ No newline at end of file
+    var color = mix(base, key, a)* 0.3 + mix(base, fill, b) * 0.7;
+    //in model space, facing the camera
+    let n_model = normalize(cross(dpdy(in.model_position), dpdx(in.model_position)));
+    if (settings.shading == 0u && settings.surface == 1u) {
+        color = pbr_color(n_model, view_direction());
+    } else if (settings.shading == 0u && settings.surface == 2u) {
+        color = matcap_color(n_model);
+    }
+    if (light.shadows > 0.0) {
+        //faces turned away from the light are in their own shadow
+        let lit = shadow_visibility(in.model_position) * smoothStep(0.0, 0.15, dot(n_model, light.direction));
+        color = color * mix(0.55, 1.0, lit);
+    }
+    if (in.shell == settings.selected_shell) {
+        color = mix(color, vec3<f32>(1.0, 0.6, 0.1), 0.35);
+    }
+    return color;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = surface_color(in);
+    //discarding only after the derivatives above keeps them in uniform control flow
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+//X-ray with weighted blended order-independent transparency (McGuire and Bavoil). Every layer adds its
+//weighted premultiplied colour to the accumulation target and multiplies the revealage target by
+//1 - alpha, and xray.wgsl divides the sums out. Layers nearer the camera weigh more.
+struct XRayOutput {
+    [[location(0)]] accumulation: vec4<f32>;
+    [[location(1)]] revealage: f32;
+};
+
+[[stage(fragment)]]
+fn fs_xray(in: VertexOutput) -> XRayOutput {
+    let color = surface_color(in);
+    //surfaces seen edge-on are more opaque, which brings out the outlines of inner shells
+    let n_model = normalize(cross(dpdy(in.model_position), dpdx(in.model_position)));
+    let rim = 1.0 - abs(dot(n_model, view_direction()));
+    let alpha = mix(0.15, 0.8, rim * rim);
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    //depth is reversed, so it is 1 at the near plane
+    let weight = alpha * clamp(3000.0 * pow(in.position.z, 3.0), 0.01, 3000.0);
+    var out: XRayOutput;
+    out.accumulation = vec4<f32>(color * alpha, alpha) * weight;
+    out.revealage = alpha;
+    return out;
+}
+//flat colour for faces flagged by an analysis, such as self-intersections
+[[stage(fragment)]]
+fn fs_highlight(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(1.0, 0.05, 0.05, 1.0);
+}
+
+//Section capping. With the section enabled, the model is drawn a second time without depth testing,
+//inverting the stencil for every fragment that isn't cut away. A pixel ends up with an odd count where
+//its view ray enters the solid at the plane, and the cap quad below is only drawn there.
+[[stage(fragment)]]
+fn fs_section_stencil(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+}
+
+//a square on the section plane, large enough to cover the model, generated from the vertex index
+[[stage(vertex)]]
+fn vs_section_cap([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let normal = settings.section_plane.xyz;
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(normal.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let u = normalize(cross(normal, helper)) * settings.section_size;
+    let v = normalize(cross(normal, u)) * settings.section_size;
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[index];
+    let position = settings.section_origin + u * corner.x + v * corner.y;
+    return to_clip(position);
+}
+
+[[stage(fragment)]]
+fn fs_section_cap() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(mix(colors.base.xyz, colors.key.xyz, 0.6), 1.0);
+}
+
+//flat fill for hidden-line drawing, which only needs to hide the edges behind it
+[[stage(fragment)]]
+fn fs_hidden_line(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(colors.key.xyz, 1.0);
+}
+
+//Wireframe. The edges are drawn from a separate, unindexed copy of the triangles, so each vertex is a
+//corner of exactly one triangle and its barycentric coordinate follows from the vertex index.
+struct WireOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] barycentric: vec3<f32>;
+    [[location(1)]] model_position: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_wire([[builtin(vertex_index)]] index: u32, [[location(0)]] position: vec3<f32>) -> WireOutput {
+    var out: WireOutput;
+    out.position = to_clip(position);
+    var barycentric = vec3<f32>(0.0, 0.0, 0.0);
+    barycentric[index % 3u] = 1.0;
+    out.barycentric = barycentric;
+    out.model_position = position;
+    return out;
+}
+
+//how much of the fragment lies on an edge about a pixel wide. A coordinate is 0 along the opposite
+//edge, and its screen-space derivative tells how far a pixel is in barycentric units.
+fn edge_coverage(barycentric: vec3<f32>) -> f32 {
+    let a = smoothStep(vec3<f32>(0.0, 0.0, 0.0), fwidth(barycentric) * 1.2, barycentric);
+    return 1.0 - min(min(a.x, a.y), a.z);
+}
+
+[[stage(fragment)]]
+fn fs_wire(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = edge_coverage(in.barycentric);
+    if (coverage < 0.01 || is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(colors.key.xyz, coverage);
+}
+
+//dark enough to stand out on a lit or flat surface
+fn line_color() -> vec3<f32> {
+    return mix(colors.base.xyz, colors.key.xyz, 0.15);
+}
+
+[[stage(fragment)]]
+fn fs_wire_overlay(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = edge_coverage(in.barycentric);
+    if (coverage < 0.01 || is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(line_color(), coverage);
+}
+
+//edges behind the front surface in X-ray mode, drawn faintly
+[[stage(fragment)]]
+fn fs_wire_hidden(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = edge_coverage(in.barycentric);
+    if (coverage < 0.01 || is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(line_color(), coverage * 0.3);
+}
+
+struct EdgeOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] model_position: vec3<f32>;
+};
+
+//Feature edges lie exactly on the surface, and depth bias doesn't apply to lines. Instead they are
+//moved slightly towards the camera after the model transform, where the model is about a unit across.
+[[stage(vertex)]]
+fn vs_edge([[location(0)]] position: vec3<f32>) -> EdgeOutput {
+    var out: EdgeOutput;
+    var p = camera.model * vec4<f32>(position, 1.0);
+    p.z = p.z + 0.002;
+    out.position = camera.projection * camera.view * p;
+    out.model_position = position;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_edge(in: EdgeOutput) -> [[location(0)]] vec4<f32> {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(line_color(), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_edge_hidden(in: EdgeOutput) -> [[location(0)]] vec4<f32> {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+    return vec4<f32>(line_color(), 0.3);
+}
+
+//Shadow map. The model is drawn from the key light with only depth, leaving out what the section cuts away.
+struct ShadowOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] model_position: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_shadow([[location(0)]] position: vec3<f32>) -> ShadowOutput {
+    var out: ShadowOutput;
+    out.position = light.matrix * vec4<f32>(position, 1.0);
+    out.model_position = position;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_shadow(in: ShadowOutput) {
+    if (is_cut_away(in.model_position)) {
+        discard;
+    }
+}
+
+//a square on the ground below the model, only drawn where the model shades it and faded towards the edges
+[[stage(vertex)]]
+fn vs_ground([[builtin(vertex_index)]] index: u32) -> ShadowOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[index];
+    let position = light.ground.xyz + (light.world_x.xyz * corner.x + light.world_y.xyz * corner.y) * light.ground.w;
+    var out: ShadowOutput;
+    out.position = to_clip(position);
+    out.model_position = position;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_ground(in: ShadowOutput) -> [[location(0)]] vec4<f32> {
+    let edge = length(in.model_position - light.ground.xyz) / light.ground.w;
+    let alpha = (1.0 - shadow_visibility(in.model_position)) * (1.0 - smoothStep(0.6, 1.0, edge)) * 0.5;
+    if (alpha < 0.01) {
+        discard;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, alpha);
+}
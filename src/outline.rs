@@ -0,0 +1,174 @@
+use crate::camera::Camera;
+use crate::color::Theme;
+use crate::model;
+
+/// Depth discontinuities smaller than this many pixels' worth of distance are not outlined
+const GAP_PIXELS: f32 = 2.0;
+
+/// Mirrors `OutlineSettings` in outline.wgsl, padded to 16 bytes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUniform {
+    color: [f32; 4],
+    depth_gap: f32,
+    _padding: [f32; 3],
+}
+
+/// Draws the silhouette of the model from the depth buffer, in a pass of its own since the
+/// depth texture can't be read while it is attached. With MSAA, the first sample of each pixel is used.
+pub struct Outline {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    color: [f32; 4],
+}
+
+impl Outline {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme, sample_count: u32, depth: &wgpu::Texture) -> Self {
+        let [key, _, base] = theme.get_values().get_model_colors();
+        //the same colour as the edges drawn by the model, see line_color in model.wgsl
+        let color = [0, 1, 2, 3].map(|i| base[i] + (key[i] - base[i]) * 0.15);
+
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("Outline shader"),
+                source: wgpu::ShaderSource::Wgsl(model::depth_reading_shader(include_str!("outline.wgsl"), sample_count)),
+            }
+        );
+
+        let uniform_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Outline settings buffer"),
+                size: std::mem::size_of::<OutlineUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: sample_count > 1,
+                        },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("Outline bind group layout"),
+            }
+        );
+        let bind_group = Self::bind_group(device, &bind_group_layout, &uniform_buffer, depth);
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Outline pipeline layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Outline pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        Self {
+            render_pipeline,
+            bind_group_layout,
+            bind_group,
+            uniform_buffer,
+            color,
+        }
+    }
+
+    fn bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform_buffer: &wgpu::Buffer, depth: &wgpu::Texture) -> wgpu::BindGroup {
+        let depth_view = model::depth_reading_view(depth);
+        device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("Outline bind group"),
+            }
+        )
+    }
+
+    /// Must be called whenever the depth texture is recreated, e.g. after a resize
+    pub fn set_depth(&mut self, device: &wgpu::Device, depth: &wgpu::Texture) {
+        self.bind_group = Self::bind_group(device, &self.bind_group_layout, &self.uniform_buffer, depth);
+    }
+
+    pub fn draw(&self, camera: &Camera, frame: &wgpu::SurfaceTexture, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+        let uniform = OutlineUniform {
+            color: self.color,
+            depth_gap: camera.depth_per_pixel() * GAP_PIXELS,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Outline render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: None,
+            }
+        );
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
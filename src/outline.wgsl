@@ -0,0 +1,39 @@
+//Screen-space silhouette outline. Depth changes linearly across a flat face, so its second difference
+//between neighbouring pixels is zero there and large where one surface passes behind another, or where
+//the model meets the background (cleared to 0).
+
+struct OutlineSettings {
+    color: vec4<f32>;
+    //second difference in depth that counts as a discontinuity
+    depth_gap: f32;
+};
+
+[[group(0), binding(0)]]
+var depth: texture_depth_2d;
+[[group(0), binding(1)]]
+var<uniform> outline: OutlineSettings;
+
+//a single triangle covering the screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let x = f32(index & 1u) * 4.0 - 1.0;
+    let y = f32(index >> 1u) * 4.0 - 1.0;
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+fn depth_at(pixel: vec2<i32>) -> f32 {
+    let size = textureDimensions(depth);
+    return textureLoad(depth, clamp(pixel, vec2<i32>(0, 0), size - vec2<i32>(1, 1)), 0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let d = depth_at(pixel) * 2.0;
+    let dx = depth_at(pixel - vec2<i32>(1, 0)) + depth_at(pixel + vec2<i32>(1, 0)) - d;
+    let dy = depth_at(pixel - vec2<i32>(0, 1)) + depth_at(pixel + vec2<i32>(0, 1)) - d;
+    if (max(abs(dx), abs(dy)) <= outline.depth_gap) {
+        discard;
+    }
+    return outline.color;
+}
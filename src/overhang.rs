@@ -0,0 +1,117 @@
+use std::fmt;
+use nalgebra_glm as glm;
+use glm::DVec3;
+use crate::loader::{ModelBounds, ModelData};
+use crate::units::{BuildDirection, Units};
+
+/// Overhang angle used when none is given, in degrees from vertical
+pub const DEFAULT_THRESHOLD: f32 = 45.0;
+
+/// Points within this fraction of the bounding box diagonal of the build plate rest on it. Looser
+/// than welding, since the shader compares positions interpolated across each face.
+const PLATE_TOLERANCE: f32 = 1e-5;
+
+/// Downward-facing surface that needs support when printed along a given direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverhangReport {
+    pub direction: BuildDirection,
+    /// Degrees from vertical beyond which a face counts as an overhang
+    pub threshold: f32,
+    pub area: f64,
+    pub faces: usize,
+    pub total_area: f64,
+    pub units: Units,
+}
+
+impl fmt::Display for OverhangReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = if self.total_area > 0.0 { 100.0 * self.area / self.total_area } else { 0.0 };
+        write!(f, "Overhangs beyond {}° building along {}: {:.3} {}² ({:.1}% of the surface, {} faces)",
+            self.threshold, self.direction, self.area, self.units, percent, self.faces)
+    }
+}
+
+/// Height of the build plate along `direction`, i.e. the lowest point of the model
+pub fn build_plate(data: &ModelData, direction: BuildDirection) -> f32 {
+    let d = direction.vector();
+    data.vertices.iter()
+        .map(|v| v.pos[0] * d[0] + v.pos[1] * d[1] + v.pos[2] * d[2])
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Distance above the build plate within which a face still rests on it, in model units
+pub fn plate_tolerance(bounds: &ModelBounds) -> f32 {
+    bounds.diagonal() * PLATE_TOLERANCE
+}
+
+/// Sums the area of every face that points down by more than `threshold` degrees from vertical.
+/// Faces resting on the build plate are supported by it and not counted. This matches the
+/// `Overhang` shading in model.wgsl, which is given the same plate and tolerance.
+pub fn find_overhangs(data: &ModelData, direction: BuildDirection, threshold: f32) -> OverhangReport {
+    let up = DVec3::from(direction.vector().map(|x| x as f64));
+    let plate = build_plate(data, direction) as f64;
+    let tolerance = plate_tolerance(&data.bounds) as f64;
+    //a face overhangs when the sine of its angle below horizontal exceeds that of the threshold
+    let limit = (threshold as f64).to_radians().sin();
+
+    let mut report = OverhangReport { direction, threshold, area: 0.0, faces: 0, total_area: 0.0, units: data.units };
+    for tri in data.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| DVec3::from(data.vertices[tri[i] as usize].pos.map(|x| x as f64)));
+        let normal = (b - a).cross(&(c - a));
+        let area = normal.magnitude() / 2.0;
+        report.total_area += area;
+        if area == 0.0 {
+            continue;
+        }
+
+        let on_plate = [a, b, c].iter().all(|p| p.dot(&up) - plate <= tolerance);
+        if !on_plate && -normal.dot(&up) / (2.0 * area) > limit {
+            report.area += area;
+            report.faces += 1;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use crate::loader::{Loader, ModelData, Vertex};
+    use crate::units::BuildDirection;
+    use super::find_overhangs;
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    #[test]
+    fn test_cube_on_plate() {
+        let data = cube();
+        for direction in ["z", "-z", "x", "-x", "y", "-y"] {
+            let report = find_overhangs(&data, direction.parse().unwrap(), 45.0);
+            assert_eq!(report.faces, 0);
+            assert!((report.total_area - 2400.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_raised_cube() {
+        let mut data = cube();
+        //a second cube floating above the first has an unsupported bottom face
+        let offset = data.vertices.len() as u32;
+        let moved: Vec<Vertex> = data.vertices.iter()
+            .map(|v| Vertex { pos: [v.pos[0], v.pos[1], v.pos[2] + 50.0] })
+            .collect();
+        data.vertices.extend(moved);
+        let moved_indices: Vec<u32> = data.indices.iter().map(|i| i + offset).collect();
+        data.indices.extend(moved_indices);
+        data.bounds.z.1 += 50.0;
+
+        let report = find_overhangs(&data, BuildDirection::PosZ, 45.0);
+        assert_eq!(report.faces, 2);
+        assert!((report.area - 400.0).abs() < 1e-6);
+        //vertical walls never overhang, even with a threshold of zero
+        assert_eq!(find_overhangs(&data, BuildDirection::PosZ, 0.0).faces, 2);
+        assert_eq!(find_overhangs(&data, BuildDirection::NegZ, 45.0).faces, 2);
+    }
+}
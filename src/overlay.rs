@@ -0,0 +1,73 @@
+use wgpu_glyph::{ab_glyph, GlyphBrush, GlyphBrushBuilder, Section, Text};
+use crate::color::{Color, Theme};
+
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+const FONT_SIZE: f32 = 16.0;
+const MARGIN: f32 = 12.0;
+
+/// Where a block of overlay text is placed on screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    BottomLeft,
+    /// Top left corner of the text at a position in pixels, e.g. next to a point on the model
+    At(f32, f32),
+}
+
+/// Draws text on top of the rendered scene. Text is queued every frame and discarded after drawing.
+pub struct Overlay {
+    brush: GlyphBrush<()>,
+    staging_belt: wgpu::util::StagingBelt,
+    color: Color,
+    blocks: Vec<(Anchor, String)>,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme) -> Self {
+        let font = ab_glyph::FontArc::try_from_slice(FONT).expect("Could not load overlay font");
+        let brush = GlyphBrushBuilder::using_font(font).build(device, config.format);
+
+        Self {
+            brush,
+            staging_belt: wgpu::util::StagingBelt::new(1024),
+            color: theme.get_values().get_text_color(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Queue a block of (possibly multi-line) text for the next frame
+    pub fn queue(&mut self, anchor: Anchor, text: String) {
+        self.blocks.push((anchor, text));
+    }
+
+    pub fn draw(&mut self, device: &wgpu::Device, frame: &wgpu::SurfaceTexture, encoder: &mut wgpu::CommandEncoder, width: u32, height: u32) {
+        if self.blocks.is_empty() {
+            return;
+        }
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let line_height = FONT_SIZE * 1.2;
+
+        for (anchor, text) in self.blocks.drain(..) {
+            let position = match anchor {
+                Anchor::TopLeft => (MARGIN, MARGIN),
+                Anchor::BottomLeft => (MARGIN, height as f32 - MARGIN - line_height * text.lines().count() as f32),
+                Anchor::At(x, y) => (x, y),
+            };
+            self.brush.queue(Section {
+                screen_position: position,
+                bounds: (width as f32, height as f32),
+                text: vec![Text::new(&text).with_color(self.color).with_scale(FONT_SIZE)],
+                ..Section::default()
+            });
+        }
+
+        self.brush.draw_queued(device, &mut self.staging_belt, encoder, &view, width, height)
+            .expect("Could not draw overlay text");
+        self.staging_belt.finish();
+    }
+
+    /// Must be called after the frame's command buffer has been submitted, so staging buffers can be reused
+    pub fn recall(&mut self) {
+        tokio::spawn(self.staging_belt.recall());
+    }
+}
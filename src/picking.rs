@@ -0,0 +1,108 @@
+use std::ops::Range;
+use std::thread::JoinHandle;
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3};
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::loader::ModelData;
+use crate::section::SectionPlane;
+
+/// A point on the model surface under the cursor
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pick {
+    /// Index of the triangle in `ModelData::indices` (divided by 3)
+    pub triangle: u32,
+    /// Hit position in model coordinates, i.e. the coordinates of the STL file
+    pub point: Vec3,
+    /// Unit normal of the picked triangle, following its winding
+    pub normal: Vec3,
+}
+
+enum PickerBvh {
+    Building(JoinHandle<Bvh>),
+    Ready(Bvh),
+}
+
+/// Finds what is under the cursor by casting a ray through the inverse camera matrix against a BVH
+/// of the model. The BVH is built on a background thread so that it doesn't delay the first frame;
+/// picking before it is done waits for it.
+pub struct Picker {
+    bvh: Option<PickerBvh>,
+}
+
+impl Picker {
+    pub fn new(data: &ModelData) -> Self {
+        let vertices = data.vertices.clone();
+        let indices = data.indices.clone();
+        let handle = std::thread::spawn(move || Bvh::new(&vertices, &indices));
+        Self { bvh: Some(PickerBvh::Building(handle)) }
+    }
+
+    fn bvh(&mut self) -> &Bvh {
+        let bvh = match self.bvh.take() {
+            Some(PickerBvh::Building(handle)) => handle.join().expect("Could not build the picking BVH"),
+            Some(PickerBvh::Ready(bvh)) => bvh,
+            None => unreachable!("the BVH is only taken out here"),
+        };
+        match self.bvh.insert(PickerBvh::Ready(bvh)) {
+            PickerBvh::Ready(bvh) => bvh,
+            PickerBvh::Building(_) => unreachable!(),
+        }
+    }
+
+    /// Nearest visible surface point under a normalized cursor position (-1...1). Triangles in the
+    /// `hidden` index ranges and points cut away by the section plane are looked through.
+    pub fn pick(&mut self, camera: &Camera, cursor: Vec2, section: Option<&SectionPlane>, hidden: &[Range<u32>]) -> Option<Pick> {
+        let (origin, direction) = camera.ray(cursor);
+        let is_hidden = |t: u32| hidden.iter().any(|r| r.contains(&(t * 3)));
+        let bvh = self.bvh();
+
+        let mut min_distance = 0.0;
+        loop {
+            let hit = bvh.cast_ray(&origin, &direction, min_distance, is_hidden)?;
+            let point = origin + direction * hit.distance;
+            if section.is_some_and(|s| s.cuts_away(&point)) {
+                min_distance = hit.distance;
+                continue;
+            }
+            let tri = bvh.triangle(hit.triangle);
+            let normal = (tri[1] - tri[0]).cross(&(tri[2] - tri[0])).normalize();
+            return Some(Pick { triangle: hit.triangle, point, normal });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use nalgebra_glm::{Vec2, Vec3};
+    use crate::camera::Camera;
+    use crate::loader::Loader;
+    use crate::section::SectionPlane;
+    use super::Picker;
+
+    #[test]
+    fn test_pick_cube() {
+        let data = Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run();
+        let mut camera = Camera::new(800.0, 600.0);
+        //the camera starts out looking at the front of the model, along +Y
+        camera.fit_verts(&data.bounds);
+        let mut picker = Picker::new(&data);
+
+        let pick = picker.pick(&camera, Vec2::new(0.0, 0.0), None, &[]).unwrap();
+        assert!((pick.point - Vec3::new(-45.0, 40.0, 10.0)).magnitude() < 1e-3, "{:?}", pick.point);
+        assert!((pick.normal - Vec3::new(0.0, -1.0, 0.0)).magnitude() < 1e-5);
+        assert!(picker.pick(&camera, Vec2::new(0.9, 0.9), None, &[]).is_none());
+
+        //with the front half cut away, the ray hits the inside of the back face
+        let mut section = SectionPlane::axis(1, &data.bounds);
+        section.flip();
+        let pick = picker.pick(&camera, Vec2::new(0.0, 0.0), Some(&section), &[]).unwrap();
+        assert!((pick.point.y - 60.0).abs() < 1e-3);
+
+        //hiding every triangle leaves nothing to pick
+        let half = data.indices.len() as u32 / 2;
+        let hidden = [0..half, half..half * 2];
+        assert!(picker.pick(&camera, Vec2::new(0.0, 0.0), None, &hidden).is_none());
+    }
+}
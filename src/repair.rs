@@ -0,0 +1,488 @@
+use std::collections::VecDeque;
+use std::fmt;
+use ahash::{AHashMap, AHashSet};
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3, DVec3};
+use crate::loader::{ModelBounds, ModelData, Vertex};
+
+/// Vertices closer than this fraction of the bounding box diagonal are welded together.
+const WELD_TOLERANCE: f32 = 1e-6;
+
+/// Boundary loops longer than this are not considered "simple" holes and are left open.
+const MAX_HOLE_EDGES: usize = 512;
+
+/// Tally of every change made by [`ModelData::repair`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepairSummary {
+    pub vertices_welded: usize,
+    pub degenerate_triangles: usize,
+    pub duplicate_triangles: usize,
+    pub triangles_flipped: usize,
+    pub shells_inverted: usize,
+    pub holes_filled: usize,
+    pub triangles_added: usize,
+}
+
+impl RepairSummary {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for RepairSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No problems found");
+        }
+        writeln!(f, "Vertices welded: {}", self.vertices_welded)?;
+        writeln!(f, "Degenerate triangles removed: {}", self.degenerate_triangles)?;
+        writeln!(f, "Duplicate triangles removed: {}", self.duplicate_triangles)?;
+        writeln!(f, "Triangles flipped: {}", self.triangles_flipped)?;
+        writeln!(f, "Inside-out shells reversed: {}", self.shells_inverted)?;
+        write!(f, "Holes filled: {} ({} triangles added)", self.holes_filled, self.triangles_added)
+    }
+}
+
+impl ModelData {
+    /// Cleans up the mesh in place. The steps are run in this order, since each one relies on the previous:
+    /// 1. weld coincident vertices (the loader workers each deduplicate independently, so seams remain)
+    /// 2. drop degenerate and duplicate triangles
+    /// 3. make the winding consistent across each connected component
+    /// 4. fill simple holes by triangulating their boundary loops
+    /// 5. flip any component whose signed volume is negative so that every shell faces outwards
+    pub fn repair(&mut self) -> RepairSummary {
+        let mut summary = RepairSummary::default();
+        let tolerance = weld_tolerance(&ModelBounds::from_vertices(&self.vertices));
+
+        let mut triangles = self.weld(tolerance, &mut summary);
+        triangles = remove_degenerate(&self.vertices, triangles, tolerance, &mut summary);
+        triangles = remove_duplicates(triangles, &mut summary);
+
+        let components = unify_winding(&mut triangles, &mut summary);
+        fill_holes(&self.vertices, &mut triangles, &mut summary);
+        orient_outward(&self.vertices, &mut triangles, components, &mut summary);
+
+        self.compact(triangles);
+        summary
+    }
+
+    /// Merges vertices that fall into the same cell of a grid with the given spacing.
+    fn weld(&self, tolerance: f32, summary: &mut RepairSummary) -> Vec<[u32; 3]> {
+        let remap = weld_map(&self.vertices, tolerance);
+        summary.vertices_welded = remap.iter().enumerate().filter(|(i, r)| *i as u32 != **r).count();
+
+        self.indices.chunks_exact(3)
+            .map(|tri| [remap[tri[0] as usize], remap[tri[1] as usize], remap[tri[2] as usize]])
+            .collect()
+    }
+
+    /// Drops unreferenced vertices and rebuilds the index buffer and bounds.
+    fn compact(&mut self, triangles: Vec<[u32; 3]>) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+
+        for idx in triangles.iter().flatten() {
+            let new_idx = &mut remap[*idx as usize];
+            if *new_idx == u32::MAX {
+                *new_idx = vertices.len() as u32;
+                vertices.push(self.vertices[*idx as usize]);
+            }
+            indices.push(*new_idx);
+        }
+        self.bounds = ModelBounds::from_vertices(&vertices);
+        self.vertices = vertices;
+        self.indices = indices;
+    }
+}
+
+/// Tolerance used to weld the vertices of a model with the given bounds
+pub fn weld_tolerance(bounds: &ModelBounds) -> f32 {
+    bounds.diagonal() * WELD_TOLERANCE
+}
+
+/// Maps every vertex to the first earlier vertex no further away than the tolerance, or to itself if
+/// there is none. Only vertices that map to themselves are welded to, so every vertex ends up within
+/// the tolerance of the one it maps to. A tolerance of zero only merges exactly equal positions.
+pub fn weld_map(vertices: &[Vertex], tolerance: f32) -> Vec<u32> {
+    if tolerance <= 0.0 {
+        let mut exact: AHashMap<[u32; 3], u32> = AHashMap::with_capacity(vertices.len());
+        //adding zero turns -0.0 into 0.0, which has a different bit pattern
+        return vertices.iter().enumerate()
+            .map(|(i, v)| *exact.entry(v.pos.map(|x| (x + 0.0).to_bits())).or_insert(i as u32))
+            .collect();
+    }
+
+    //with cells as large as the tolerance, any vertex close enough is in the same cell or a neighbouring one
+    let mut cells: AHashMap<[i64; 3], Vec<u32>> = AHashMap::with_capacity(vertices.len());
+    vertices.iter().enumerate().map(|(i, v)| {
+        let p = Vec3::from(v.pos);
+        let cell = v.pos.map(|x| (x / tolerance).floor() as i64);
+        let mut nearest: Option<u32> = None;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let found = cells.get(&[cell[0] + dx, cell[1] + dy, cell[2] + dz]).into_iter().flatten()
+                        .find(|j| (position(vertices, **j) - p).magnitude_squared() <= tolerance * tolerance);
+                    if let Some(j) = found {
+                        nearest = Some(nearest.map_or(*j, |n| n.min(*j)));
+                    }
+                }
+            }
+        }
+        nearest.unwrap_or_else(|| {
+            cells.entry(cell).or_default().push(i as u32);
+            i as u32
+        })
+    }).collect()
+}
+
+fn position(vertices: &[Vertex], idx: u32) -> Vec3 {
+    Vec3::from(vertices[idx as usize].pos)
+}
+
+fn flip(tri: &mut [u32; 3]) {
+    tri.swap(1, 2);
+}
+
+fn edges(tri: &[u32; 3]) -> [(u32, u32); 3] {
+    [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])]
+}
+
+fn undirected(edge: (u32, u32)) -> (u32, u32) {
+    (edge.0.min(edge.1), edge.0.max(edge.1))
+}
+
+fn has_directed_edge(tri: &[u32; 3], edge: (u32, u32)) -> bool {
+    edges(tri).contains(&edge)
+}
+
+/// Maps every undirected edge to the triangles that use it.
+fn edge_map(triangles: &[[u32; 3]]) -> AHashMap<(u32, u32), Vec<usize>> {
+    let mut map: AHashMap<(u32, u32), Vec<usize>> = AHashMap::with_capacity(triangles.len() * 3 / 2);
+    for (t, tri) in triangles.iter().enumerate() {
+        for edge in edges(tri) {
+            map.entry(undirected(edge)).or_default().push(t);
+        }
+    }
+    map
+}
+
+fn remove_degenerate(vertices: &[Vertex], triangles: Vec<[u32; 3]>, tolerance: f32, summary: &mut RepairSummary) -> Vec<[u32; 3]> {
+    let before = triangles.len();
+    let min_area = tolerance * tolerance;
+    let triangles: Vec<_> = triangles.into_iter().filter(|tri| {
+        if tri[0] == tri[1] || tri[1] == tri[2] || tri[2] == tri[0] {
+            return false;
+        }
+        let [a, b, c] = tri.map(|i| position(vertices, i));
+        (b - a).cross(&(c - a)).magnitude() / 2.0 > min_area
+    }).collect();
+    summary.degenerate_triangles = before - triangles.len();
+    triangles
+}
+
+/// Removes triangles that use the same three vertices as an earlier one, regardless of winding.
+fn remove_duplicates(triangles: Vec<[u32; 3]>, summary: &mut RepairSummary) -> Vec<[u32; 3]> {
+    let before = triangles.len();
+    let mut seen = AHashSet::with_capacity(triangles.len());
+    let triangles: Vec<_> = triangles.into_iter().filter(|tri| {
+        let mut key = *tri;
+        key.sort_unstable();
+        seen.insert(key)
+    }).collect();
+    summary.duplicate_triangles = before - triangles.len();
+    triangles
+}
+
+/// Walks each connected component across its manifold edges, flipping any triangle that traverses
+/// a shared edge in the same direction as its neighbour. Returns the triangles of every component.
+fn unify_winding(triangles: &mut [[u32; 3]], summary: &mut RepairSummary) -> Vec<Vec<usize>> {
+    let edges_to_tris = edge_map(triangles);
+    let mut visited = vec![false; triangles.len()];
+    let mut components = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for seed in 0..triangles.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        queue.push_back(seed);
+        let mut component = Vec::new();
+
+        while let Some(t) = queue.pop_front() {
+            component.push(t);
+            for edge in edges(&triangles[t]) {
+                let neighbours = &edges_to_tris[&undirected(edge)];
+                //non-manifold edges give no reliable orientation information
+                if neighbours.len() != 2 {
+                    continue;
+                }
+                let other = if neighbours[0] == t { neighbours[1] } else { neighbours[0] };
+                if visited[other] {
+                    continue;
+                }
+                if has_directed_edge(&triangles[other], edge) {
+                    flip(&mut triangles[other]);
+                    summary.triangles_flipped += 1;
+                }
+                visited[other] = true;
+                queue.push_back(other);
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+/// Finds closed loops of boundary edges and triangulates each of them. New triangles are appended
+/// with a winding that matches the surrounding surface.
+fn fill_holes(vertices: &[Vertex], triangles: &mut Vec<[u32; 3]>, summary: &mut RepairSummary) {
+    let edges_to_tris = edge_map(triangles);
+
+    //each boundary edge a->b is walked backwards (b->a) so that the patch winds like its neighbours
+    let mut next: AHashMap<u32, u32> = AHashMap::new();
+    let mut ambiguous: AHashSet<u32> = AHashSet::new();
+    for tri in triangles.iter() {
+        for (a, b) in edges(tri) {
+            if edges_to_tris[&undirected((a, b))].len() == 1 && next.insert(b, a).is_some() {
+                //a vertex where several boundaries meet can't be resolved into simple loops
+                ambiguous.insert(b);
+            }
+        }
+    }
+
+    let mut visited: AHashSet<u32> = AHashSet::new();
+    let mut starts: Vec<u32> = next.keys().copied().collect();
+    starts.sort_unstable();
+
+    for start in starts {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut hole = vec![start];
+        let mut current = start;
+        let closed = loop {
+            visited.insert(current);
+            if ambiguous.contains(&current) || hole.len() > MAX_HOLE_EDGES {
+                break false;
+            }
+            match next.get(&current) {
+                Some(&n) if n == start => break true,
+                Some(&n) if !visited.contains(&n) => {
+                    hole.push(n);
+                    current = n;
+                },
+                _ => break false,
+            }
+        };
+
+        if closed && hole.len() >= 3 {
+            let patch = triangulate_loop(vertices, &hole);
+            summary.holes_filled += 1;
+            summary.triangles_added += patch.len();
+            triangles.extend(patch);
+        }
+    }
+}
+
+/// Ear-clipping triangulation of a boundary loop after projecting it onto its best-fit plane.
+/// Falls back to a fan if no ear can be found, which only happens for badly self-overlapping loops.
+fn triangulate_loop(vertices: &[Vertex], hole: &[u32]) -> Vec<[u32; 3]> {
+    let points: Vec<Vec3> = hole.iter().map(|i| position(vertices, *i)).collect();
+
+    //Newell's method gives a robust normal for non-planar polygons
+    let mut normal = Vec3::zeros();
+    for (i, p) in points.iter().enumerate() {
+        let q = points[(i + 1) % points.len()];
+        normal += Vec3::new((p.y - q.y) * (p.z + q.z), (p.z - q.z) * (p.x + q.x), (p.x - q.x) * (p.y + q.y));
+    }
+    let normal = if normal.magnitude() > 0.0 { normal.normalize() } else { Vec3::z() };
+    let helper = if normal.x.abs() < 0.9 { Vec3::x() } else { Vec3::y() };
+    let u = normal.cross(&helper).normalize();
+    let v = normal.cross(&u);
+    let flat: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.dot(&u), p.dot(&v))).collect();
+
+    let cross = |a: Vec2, b: Vec2, c: Vec2| (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    let inside = |p: Vec2, a: Vec2, b: Vec2, c: Vec2| cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0;
+
+    let mut remaining: Vec<usize> = (0..hole.len()).collect();
+    let mut result = Vec::with_capacity(hole.len() - 2);
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            cross(flat[a], flat[b], flat[c]) > 0.0 && !remaining.iter()
+                .filter(|&&j| j != a && j != b && j != c)
+                .any(|&j| inside(flat[j], flat[a], flat[b], flat[c]))
+        });
+        match ear {
+            Some(i) => {
+                let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+                result.push([hole[a], hole[b], hole[c]]);
+                remaining.remove(i);
+            },
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        result.push([hole[remaining[0]], hole[remaining[i]], hole[remaining[i + 1]]]);
+    }
+    result
+}
+
+/// Signed volume of a set of triangles relative to `origin`. Positive when the normals point outwards.
+pub fn signed_volume<'a, T>(vertices: &[Vertex], triangles: T, origin: DVec3) -> f64
+    where T: Iterator<Item = &'a [u32; 3]>
+{
+    triangles.map(|tri| {
+        let [a, b, c] = tri.map(|i| glm::convert::<Vec3, DVec3>(position(vertices, i)) - origin);
+        a.dot(&b.cross(&c))
+    }).sum::<f64>() / 6.0
+}
+
+fn orient_outward(vertices: &[Vertex], triangles: &mut [[u32; 3]], components: Vec<Vec<usize>>, summary: &mut RepairSummary) {
+    //hole patches were appended after the components were found, so assign them to the component of a neighbour
+    let mut component_of = vec![usize::MAX; triangles.len()];
+    for (c, tris) in components.iter().enumerate() {
+        for t in tris {
+            component_of[*t] = c;
+        }
+    }
+    let mut by_vertex: AHashMap<u32, usize> = AHashMap::new();
+    for (t, tri) in triangles.iter().enumerate() {
+        if component_of[t] != usize::MAX {
+            for idx in tri {
+                by_vertex.insert(*idx, component_of[t]);
+            }
+        }
+    }
+    let mut members = components;
+    for (t, tri) in triangles.iter().enumerate() {
+        if component_of[t] == usize::MAX {
+            if let Some(c) = tri.iter().find_map(|idx| by_vertex.get(idx)) {
+                members[*c].push(t);
+            }
+        }
+    }
+
+    for tris in members {
+        let bounds = ModelBounds::from_vertices(&tris.iter()
+            .flat_map(|t| triangles[*t])
+            .map(|i| vertices[i as usize])
+            .collect::<Vec<_>>());
+        let origin = DVec3::new(
+            (bounds.x.0 + bounds.x.1) as f64 / 2.0,
+            (bounds.y.0 + bounds.y.1) as f64 / 2.0,
+            (bounds.z.0 + bounds.z.1) as f64 / 2.0
+        );
+        let volume = signed_volume(vertices, tris.iter().map(|t| &triangles[*t]), origin);
+        if volume < 0.0 {
+            for t in &tris {
+                flip(&mut triangles[*t]);
+            }
+            summary.shells_inverted += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use nalgebra_glm::DVec3;
+    use crate::loader::{Loader, ModelData, Vertex};
+    use super::{weld_map, RepairSummary};
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    fn triangles(data: &ModelData) -> Vec<[u32; 3]> {
+        data.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect()
+    }
+
+    fn volume(data: &ModelData) -> f64 {
+        super::signed_volume(&data.vertices, triangles(data).iter(), DVec3::zeros())
+    }
+
+    #[test]
+    fn test_clean_cube_unchanged() {
+        let mut data = cube();
+        let summary = data.repair();
+        assert_eq!(summary, RepairSummary::default());
+        assert_eq!(data.indices.len(), 36);
+        assert!((volume(&data) - 8000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_weld_and_duplicates() {
+        let mut data = cube();
+        //duplicate every vertex, pointing the first triangle at the copies, then duplicate that triangle
+        let n = data.vertices.len() as u32;
+        data.vertices.extend(data.vertices.clone());
+        for idx in data.indices.iter_mut().take(3) {
+            *idx += n;
+        }
+        data.indices.extend_from_within(0..3);
+
+        let summary = data.repair();
+        assert_eq!(summary.vertices_welded, 8);
+        assert_eq!(summary.duplicate_triangles, 1);
+        assert_eq!(data.vertices.len(), 8);
+        assert_eq!(data.indices.len(), 36);
+    }
+
+    #[test]
+    fn test_weld_across_cells() {
+        //the first two are in different cells of a grid as large as the tolerance, but close enough to weld
+        let vertices = [[0.99, 0.0, 0.0], [1.01, 0.0, 0.0], [1.0, 2.0, 0.0], [-1.01, 0.0, 0.0], [1.02, 0.5, 0.0]]
+            .map(|pos| Vertex { pos });
+        assert_eq!(weld_map(&vertices, 1e-1), vec![0, 0, 2, 3, 4]);
+        assert_eq!(weld_map(&vertices, 0.0), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_degenerate_removed() {
+        let mut data = cube();
+        data.indices.extend_from_slice(&[0, 0, 1, 2, 3, 3]);
+        let summary = data.repair();
+        assert_eq!(summary.degenerate_triangles, 2);
+        assert_eq!(data.indices.len(), 36);
+    }
+
+    #[test]
+    fn test_flipped_triangle() {
+        let mut data = cube();
+        data.indices.swap(4, 5);
+        let summary = data.repair();
+        assert_eq!(summary.triangles_flipped, 1);
+        assert_eq!(summary.shells_inverted, 0);
+        assert!((volume(&data) - 8000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_inside_out() {
+        let mut data = cube();
+        for tri in data.indices.chunks_exact_mut(3) {
+            tri.swap(1, 2);
+        }
+        assert!(volume(&data) < 0.0);
+        let summary = data.repair();
+        assert_eq!(summary.shells_inverted, 1);
+        assert!((volume(&data) - 8000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_fill_hole() {
+        let mut data = cube();
+        //remove both triangles of one face
+        data.indices.drain(0..6);
+        let summary = data.repair();
+        assert_eq!(summary.holes_filled, 1);
+        assert_eq!(summary.triangles_added, 2);
+        assert_eq!(data.indices.len(), 36);
+        assert!((volume(&data) - 8000.0).abs() < 1e-3);
+    }
+}
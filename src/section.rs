@@ -0,0 +1,144 @@
+use std::fmt;
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::loader::ModelBounds;
+
+/// Number of steps it takes to move the plane through the whole model
+const STEPS_ACROSS: f32 = 100.0;
+
+/// Cross-section plane `dot(normal, p) = offset` in model coordinates. Everything on the side the
+/// normal points to is cut away.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SectionPlane {
+    pub normal: Vec3,
+    pub offset: f32,
+    center: Vec3,
+    /// Half the bounding box diagonal, which limits how far the plane can move from the centre
+    radius: f32,
+}
+
+impl SectionPlane {
+    /// Plane through the middle of the model, perpendicular to the given axis (0 = X, 1 = Y, 2 = Z)
+    pub fn axis(axis: usize, bounds: &ModelBounds) -> Self {
+        let center = Vec3::new(
+            (bounds.x.0 + bounds.x.1) / 2.0,
+            (bounds.y.0 + bounds.y.1) / 2.0,
+            (bounds.z.0 + bounds.z.1) / 2.0,
+        );
+        let mut normal = Vec3::zeros();
+        normal[axis] = 1.0;
+        Self { normal, offset: center[axis], center, radius: bounds.diagonal() / 2.0 }
+    }
+
+    /// The same plane moved to the given offset, without the limits of [`translate`](Self::translate)
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// In-plane axes `(u, v)` with `u x v = normal`, so that 2D contours keep the winding they have when
+    /// viewed from the side the normal points to. Axis planes use the other two model axes in cyclic order.
+    pub fn basis(&self) -> (Vec3, Vec3) {
+        if let Some(axis) = self.snapped_axis() {
+            let mut u = Vec3::zeros();
+            let mut v = Vec3::zeros();
+            u[(axis + 1) % 3] = 1.0;
+            v[(axis + 2) % 3] = 1.0;
+            //flipping u keeps the basis right handed for a negative normal
+            return (u * self.normal[axis], v);
+        }
+        //same construction as vs_section_cap in model.wgsl
+        let helper = if self.normal.x.abs() > 0.9 { Vec3::y() } else { Vec3::x() };
+        let u = self.normal.cross(&helper).normalize();
+        (u, self.normal.cross(&u))
+    }
+
+    /// Axis the plane is perpendicular to, if it is snapped to one
+    pub fn snapped_axis(&self) -> Option<usize> {
+        (0..3).find(|i| self.normal[*i].abs() == 1.0)
+    }
+
+    /// Whether the point is on the side that is cut away, matching `is_cut_away` in model.wgsl
+    pub fn cuts_away(&self, p: &Vec3) -> bool {
+        self.normal.dot(p) > self.offset
+    }
+
+    /// Keeps the other side of the plane instead
+    pub fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.offset = -self.offset;
+    }
+
+    /// Moves the plane along its normal, without leaving the model's bounding sphere
+    pub fn translate(&mut self, distance: f32) {
+        let middle = self.normal.dot(&self.center);
+        self.offset = (self.offset + distance).clamp(middle - self.radius, middle + self.radius);
+    }
+
+    /// Distance moved per key press or scroll step
+    pub fn step(&self) -> f32 {
+        2.0 * self.radius / STEPS_ACROSS
+    }
+
+    /// The model centre projected onto the plane, used as the centre of the cap
+    pub fn origin(&self) -> Vec3 {
+        self.center - self.normal * (self.normal.dot(&self.center) - self.offset)
+    }
+
+    /// Half the side length of a square cap that covers the model's cross-section
+    pub fn cap_size(&self) -> f32 {
+        self.radius
+    }
+
+    /// Where the plane is, e.g. `Z = 12.500`
+    pub fn position(&self) -> String {
+        match self.snapped_axis() {
+            Some(axis) => format!("{} = {:.3}", ["X", "Y", "Z"][axis], self.offset * self.normal[axis]),
+            None => format!("({:.3}, {:.3}, {:.3}) . p = {:.3}", self.normal.x, self.normal.y, self.normal.z, self.offset),
+        }
+    }
+}
+
+impl fmt::Display for SectionPlane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Section at {}", self.position())?;
+        if let Some(axis) = self.snapped_axis() {
+            let side = if self.normal[axis] < 0.0 { "-" } else { "+" };
+            write!(f, ", cutting away {}{}", side, ["X", "Y", "Z"][axis])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra_glm::Vec3;
+    use crate::loader::ModelBounds;
+    use super::SectionPlane;
+
+    fn bounds() -> ModelBounds {
+        ModelBounds { x: (0.0, 10.0), y: (-4.0, 4.0), z: (2.0, 5.0) }
+    }
+
+    #[test]
+    fn test_axis_plane() {
+        let plane = SectionPlane::axis(1, &bounds());
+        assert_eq!(plane.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(plane.offset, 0.0);
+        assert_eq!(plane.snapped_axis(), Some(1));
+        assert_eq!(plane.origin(), Vec3::new(5.0, 0.0, 3.5));
+    }
+
+    #[test]
+    fn test_flip_and_translate() {
+        let mut plane = SectionPlane::axis(2, &bounds());
+        plane.flip();
+        assert_eq!(plane.offset, -3.5);
+        assert_eq!(plane.origin(), Vec3::new(5.0, 0.0, 3.5));
+        //the flipped normal points down, so moving forwards lowers the plane
+        plane.translate(1.0);
+        assert_eq!(plane.origin(), Vec3::new(5.0, 0.0, 2.5));
+        plane.translate(-1000.0);
+        assert!((plane.origin().z - (3.5 + bounds().diagonal() / 2.0)).abs() < 1e-4);
+    }
+}
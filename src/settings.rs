@@ -0,0 +1,382 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::overhang::DEFAULT_THRESHOLD;
+use crate::units::BuildDirection;
+use crate::section::SectionPlane;
+use crate::thickness::DEFAULT_MIN_THICKNESS;
+
+/// How the surface of the model is coloured
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// Key/fill lighting using the colours from the current [`Theme`](crate::color::Theme)
+    Theme,
+    /// Every shell gets its own colour
+    Shells,
+    /// Heatmap of the angle between each face and the build direction, with overhangs in red
+    Overhang,
+    /// Colour ramp of the wall thickness, with walls thinner than the minimum in red
+    Thickness,
+    /// Diverging colour map of the mean curvature, concave in blue and convex in red
+    MeanCurvature,
+    /// Diverging colour map of the Gaussian curvature, saddles in blue and domes in red
+    GaussianCurvature,
+}
+
+impl Shading {
+    /// Value of `settings.shading` in model.wgsl
+    fn shader_id(&self) -> u32 {
+        match self {
+            Shading::Theme => 0,
+            Shading::Shells => 1,
+            Shading::Overhang => 2,
+            Shading::Thickness => 3,
+            Shading::MeanCurvature => 4,
+            Shading::GaussianCurvature => 5,
+        }
+    }
+}
+
+/// Physically based material presets, lit by the environment map. Base colours are linear.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Material {
+    Plastic,
+    Aluminium,
+    Resin,
+    Steel,
+}
+
+impl Material {
+    /// The next preset, or `None` after the last to go back to theme shading
+    pub fn next(material: Option<Material>) -> Option<Material> {
+        match material {
+            None => Some(Material::Plastic),
+            Some(Material::Plastic) => Some(Material::Aluminium),
+            Some(Material::Aluminium) => Some(Material::Resin),
+            Some(Material::Resin) => Some(Material::Steel),
+            Some(Material::Steel) => None,
+        }
+    }
+
+    pub fn base_color(&self) -> [f32; 3] {
+        match self {
+            Material::Plastic => [0.42, 0.45, 0.5],
+            Material::Aluminium => [0.91, 0.92, 0.92],
+            Material::Resin => [0.75, 0.45, 0.12],
+            Material::Steel => [0.56, 0.57, 0.58],
+        }
+    }
+
+    pub fn metallic(&self) -> f32 {
+        match self {
+            Material::Plastic | Material::Resin => 0.0,
+            Material::Aluminium | Material::Steel => 1.0,
+        }
+    }
+
+    pub fn roughness(&self) -> f32 {
+        match self {
+            Material::Plastic => 0.45,
+            Material::Aluminium => 0.35,
+            Material::Resin => 0.15,
+            Material::Steel => 0.25,
+        }
+    }
+}
+
+impl FromStr for Material {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "plastic" => Ok(Material::Plastic),
+            "aluminium" | "aluminum" => Ok(Material::Aluminium),
+            "resin" => Ok(Material::Resin),
+            "steel" => Ok(Material::Steel),
+            _ => Err(format!("Unknown material '{}', expected one of plastic, aluminium, resin, steel", s)),
+        }
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Material::Plastic => "Plastic",
+            Material::Aluminium => "Aluminium",
+            Material::Resin => "Resin",
+            Material::Steel => "Steel",
+        })
+    }
+}
+
+/// Whether and how far the reference grid on the Z=0 plane is drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GridMode {
+    Off,
+    /// Fades out far beyond the model
+    Infinite,
+    /// Covers the build plate, or the area around the model without one
+    Bounded,
+}
+
+impl GridMode {
+    pub fn next(&self) -> Self {
+        match self {
+            GridMode::Off => GridMode::Infinite,
+            GridMode::Infinite => GridMode::Bounded,
+            GridMode::Bounded => GridMode::Off,
+        }
+    }
+}
+
+impl FromStr for GridMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(GridMode::Off),
+            "infinite" => Ok(GridMode::Infinite),
+            "bounded" => Ok(GridMode::Bounded),
+            _ => Err(format!("Unknown grid '{}', expected one of off, infinite, bounded", s)),
+        }
+    }
+}
+
+impl fmt::Display for GridMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GridMode::Off => "Off",
+            GridMode::Infinite => "Infinite",
+            GridMode::Bounded => "Bounded",
+        })
+    }
+}
+
+/// Printer presets, whose build volume is drawn as a wire box standing on the Z=0 plane
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Printer {
+    PrusaMk4,
+    PrusaMini,
+    BambuX1,
+    Ender3,
+    UltimakerS5,
+    Form3,
+}
+
+impl Printer {
+    /// The next preset, or `None` after the last to hide the build volume
+    pub fn next(printer: Option<Printer>) -> Option<Printer> {
+        match printer {
+            None => Some(Printer::PrusaMk4),
+            Some(Printer::PrusaMk4) => Some(Printer::PrusaMini),
+            Some(Printer::PrusaMini) => Some(Printer::BambuX1),
+            Some(Printer::BambuX1) => Some(Printer::Ender3),
+            Some(Printer::Ender3) => Some(Printer::UltimakerS5),
+            Some(Printer::UltimakerS5) => Some(Printer::Form3),
+            Some(Printer::Form3) => None,
+        }
+    }
+
+    /// Width, depth and height of the build volume in millimetres
+    pub fn build_volume(&self) -> [f32; 3] {
+        match self {
+            Printer::PrusaMk4 => [250.0, 210.0, 220.0],
+            Printer::PrusaMini => [180.0, 180.0, 180.0],
+            Printer::BambuX1 => [256.0, 256.0, 256.0],
+            Printer::Ender3 => [220.0, 220.0, 250.0],
+            Printer::UltimakerS5 => [330.0, 240.0, 300.0],
+            Printer::Form3 => [145.0, 145.0, 185.0],
+        }
+    }
+}
+
+impl FromStr for Printer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mk4" => Ok(Printer::PrusaMk4),
+            "mini" => Ok(Printer::PrusaMini),
+            "x1" => Ok(Printer::BambuX1),
+            "ender3" => Ok(Printer::Ender3),
+            "s5" => Ok(Printer::UltimakerS5),
+            "form3" => Ok(Printer::Form3),
+            _ => Err(format!("Unknown printer '{}', expected one of mk4, mini, x1, ender3, s5, form3", s)),
+        }
+    }
+}
+
+impl fmt::Display for Printer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Printer::PrusaMk4 => "Prusa MK4",
+            Printer::PrusaMini => "Prusa MINI",
+            Printer::BambuX1 => "Bambu Lab X1",
+            Printer::Ender3 => "Creality Ender-3",
+            Printer::UltimakerS5 => "Ultimaker S5",
+            Printer::Form3 => "Formlabs Form 3",
+        })
+    }
+}
+
+/// Which passes draw the model. Edges are found from barycentric coordinates in the fragment shader,
+/// so none of these need `PolygonMode::Line`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    Shaded,
+    /// Every edge, including those on the far side of the model
+    Wireframe,
+    /// Visible edges drawn over the shaded surface
+    ShadedWireframe,
+    /// Visible edges over a flat fill that hides the ones behind it
+    HiddenLine,
+}
+
+impl RenderMode {
+    pub fn next(&self) -> Self {
+        match self {
+            RenderMode::Shaded => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::ShadedWireframe,
+            RenderMode::ShadedWireframe => RenderMode::HiddenLine,
+            RenderMode::HiddenLine => RenderMode::Shaded,
+        }
+    }
+
+    /// Whether the triangle edges are drawn
+    pub fn has_edges(&self) -> bool {
+        *self != RenderMode::Shaded
+    }
+
+    /// Whether the surface is drawn, which also means it hides what is behind it
+    pub fn has_fill(&self) -> bool {
+        *self != RenderMode::Wireframe
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RenderMode::Shaded => "Shaded",
+            RenderMode::Wireframe => "Wireframe",
+            RenderMode::ShadedWireframe => "Shaded with edges",
+            RenderMode::HiddenLine => "Hidden line",
+        })
+    }
+}
+
+/// Viewer settings that affect how the model is drawn
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    /// Outline the sharp edges found by [`feature_edges`](crate::edges::feature_edges)
+    pub feature_edges: bool,
+    /// Outline the silhouette, found from depth discontinuities
+    pub silhouette: bool,
+    /// Darken creases and corners with screen-space ambient occlusion
+    pub ambient_occlusion: bool,
+    /// Shade the model with shadows cast from the key light
+    pub shadows: bool,
+    /// Draw the shadow of the model on the ground below it
+    pub ground_shadow: bool,
+    /// Draw the model semi-transparent, showing inner shells and the edges behind the front surface
+    pub xray: bool,
+    pub grid: GridMode,
+    /// Printer whose build volume is drawn around the model
+    pub printer: Option<Printer>,
+    pub shading: Shading,
+    /// Shade with a physically based material instead of the theme colours, when `shading` is `Theme`
+    pub material: Option<Material>,
+    /// Index of the matcap to shade with instead, which takes precedence over `material`
+    pub matcap: Option<usize>,
+    pub selected_shell: Option<u32>,
+    pub build_direction: BuildDirection,
+    /// Degrees from vertical beyond which a downward face is flagged as an overhang
+    pub overhang_threshold: f32,
+    /// Height of the lowest point of the model along the build direction
+    pub build_plate: f32,
+    /// Faces this close to the build plate rest on it
+    pub plate_tolerance: f32,
+    /// Walls thinner than this are flagged, in model units
+    pub min_thickness: f32,
+    /// Curvature shown at the ends of the curvature colour map, beyond which it is clamped
+    pub curvature_range: f32,
+    /// Cross-section plane, if the model is cut open
+    pub section: Option<SectionPlane>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            mode: RenderMode::Shaded,
+            feature_edges: false,
+            silhouette: false,
+            ambient_occlusion: false,
+            shadows: false,
+            ground_shadow: false,
+            xray: false,
+            grid: GridMode::Off,
+            printer: None,
+            shading: Shading::Theme,
+            material: None,
+            matcap: None,
+            selected_shell: None,
+            build_direction: BuildDirection::PosZ,
+            overhang_threshold: DEFAULT_THRESHOLD,
+            build_plate: 0.0,
+            plate_tolerance: 0.0,
+            min_thickness: DEFAULT_MIN_THICKNESS,
+            curvature_range: 1.0,
+            section: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn uniform(&self) -> SettingsUniform {
+        SettingsUniform {
+            shading: self.shading.shader_id(),
+            selected_shell: self.selected_shell.unwrap_or(u32::MAX),
+            overhang_threshold: self.overhang_threshold,
+            build_plate: self.build_plate,
+            build_direction: self.build_direction.vector(),
+            min_thickness: self.min_thickness,
+            //a zero normal disables the section
+            section_plane: self.section.map_or([0.0; 4], |p| [p.normal.x, p.normal.y, p.normal.z, p.offset]),
+            section_origin: self.section.map_or([0.0; 3], |p| p.origin().into()),
+            section_size: self.section.map_or(0.0, |p| p.cap_size()),
+            material: self.material.map_or([0.0; 4], |m| {
+                let [r, g, b] = m.base_color();
+                [r, g, b, m.metallic()]
+            }),
+            roughness: self.material.map_or(0.0, |m| m.roughness()),
+            //value of `settings.surface` in model.wgsl
+            surface: match (self.matcap, self.material) {
+                (Some(_), _) => 2,
+                (None, Some(_)) => 1,
+                (None, None) => 0,
+            },
+            curvature_range: self.curvature_range,
+            plate_tolerance: self.plate_tolerance,
+        }
+    }
+}
+
+/// Mirrors `Settings` in model.wgsl. WGSL aligns vec3s to 16 bytes, so every `[f32; 3]` is followed
+/// by a scalar that fills the rest of those 16 bytes. The struct is padded to a multiple of 16 bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SettingsUniform {
+    shading: u32,
+    selected_shell: u32,
+    overhang_threshold: f32,
+    build_plate: f32,
+    build_direction: [f32; 3],
+    min_thickness: f32,
+    section_plane: [f32; 4],
+    section_origin: [f32; 3],
+    section_size: f32,
+    material: [f32; 4],
+    roughness: f32,
+    surface: u32,
+    curvature_range: f32,
+    plate_tolerance: f32,
+}
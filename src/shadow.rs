@@ -0,0 +1,206 @@
+use nalgebra_glm as glm;
+use glm::{Mat4, Quat, Vec3};
+use crate::loader::ModelBounds;
+
+/// Width and height of the shadow map in texels
+const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The ground plane extends this far past the bounding sphere of the model, relative to its radius
+const GROUND_EXTENT: f32 = 1.5;
+
+/// Direction towards the key light in the world, where Z is up: above the model, and to the front
+/// right of where the camera starts out
+pub fn key_light_direction() -> Vec3 {
+    Vec3::new(0.35, -0.5, 1.0).normalize()
+}
+
+/// Orthographic projection from the key light onto the shadow map, fitted to the bounding sphere of
+/// the model, both in the world. The depth range reaches down to the ground plane below the lowest point, so that the
+/// shadow of the whole model falls inside it. Unlike the camera, depth is not reversed.
+pub fn light_matrix(bounds: &ModelBounds, direction: &Vec3) -> Mat4 {
+    let center = Vec3::new(
+        (bounds.x.0 + bounds.x.1) / 2.0,
+        (bounds.y.0 + bounds.y.1) / 2.0,
+        (bounds.z.0 + bounds.z.1) / 2.0,
+    );
+    let radius = bounds.diagonal() / 2.0;
+    let eye = center + direction * radius;
+    //a point on the sphere is at most two radii above the ground, and light travels 1 / direction.z
+    //times as far as it falls
+    let far = radius * 2.0 + radius * 2.0 / direction.z;
+    let view = glm::look_at_rh(&eye, &center, &Vec3::z());
+    glm::ortho_rh_zo(-radius, radius, -radius, radius, 0.0, far) * view
+}
+
+/// Mirrors `Light` in model.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    matrix: [f32; 16],
+    direction: [f32; 3],
+    /// 1 to shade the model with shadows, 0 to leave it alone
+    shadows: f32,
+    /// Centre of the ground plane in xyz and half its size in w
+    ground: [f32; 4],
+    /// The world's X and Y axes in model coordinates, in xyz. The ground lies along them, and the
+    /// environment is turned so that the world's Z is up.
+    world_axes: [[f32; 4]; 2],
+}
+
+/// Binding of the light uniform, shared by the group used while rendering the map and the group
+/// sampling it, which is created along with the rest of the lighting by [`Model`](crate::model::Model)
+pub const LIGHT_ENTRY: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
+    binding: 2,
+    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    },
+    count: None
+};
+
+/// The shadow map and the key light it is rendered from
+pub struct ShadowMap {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub light_buffer: wgpu::Buffer,
+    /// Layout of the group holding only the light, bound while rendering the map itself
+    pub pass_bind_group_layout: wgpu::BindGroupLayout,
+    pub pass_bind_group: wgpu::BindGroup,
+    matrix: Mat4,
+    /// Towards the key light, in model coordinates
+    direction: Vec3,
+    ground: [f32; 4],
+    world_axes: [[f32; 4]; 2],
+}
+
+impl ShadowMap {
+    /// Creates the map for a model whose up axis `up` turns to Z
+    pub fn new(device: &wgpu::Device, bounds: &ModelBounds, up: &Quat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow map"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        //linear filtering compares the four nearest texels, which smooths the PCF in the shader further
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label: Some("Shadow map sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                ..Default::default()
+            }
+        );
+
+        let light_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Light buffer"),
+                size: std::mem::size_of::<LightUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+
+        //the map can't be bound while it is being rendered to, so this pass only sees the light
+        let pass_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[LIGHT_ENTRY],
+                label: Some("Shadow pass bind group layout"),
+            }
+        );
+        let pass_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &pass_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: light_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("Shadow pass bind group"),
+            }
+        );
+
+        //the light and the ground are laid out in the world, and turned back into the model
+        let world = bounds.rotated(up);
+        let to_model = |v: Vec3| {
+            let v = glm::quat_rotate_vec3(&glm::quat_conjugate(up), &v);
+            [v.x, v.y, v.z, 0.0]
+        };
+        let radius = bounds.diagonal() / 2.0;
+        let center = to_model(Vec3::new((world.x.0 + world.x.1) / 2.0, (world.y.0 + world.y.1) / 2.0, world.z.0));
+        Self {
+            view,
+            sampler,
+            light_buffer,
+            pass_bind_group_layout,
+            pass_bind_group,
+            matrix: light_matrix(&world, &key_light_direction()) * glm::quat_to_mat4(up),
+            direction: glm::quat_rotate_vec3(&glm::quat_conjugate(up), &key_light_direction()),
+            ground: [center[0], center[1], center[2], radius * GROUND_EXTENT],
+            world_axes: [to_model(Vec3::x()), to_model(Vec3::y())],
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, shadows: bool) {
+        let mut matrix = [0.0; 16];
+        matrix.copy_from_slice(self.matrix.as_slice());
+        let uniform = LightUniform {
+            matrix,
+            direction: self.direction.into(),
+            shadows: if shadows { 1.0 } else { 0.0 },
+            ground: self.ground,
+            world_axes: self.world_axes,
+        };
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra_glm::{Vec3, Vec4};
+    use crate::loader::ModelBounds;
+    use super::{key_light_direction, light_matrix};
+
+    #[test]
+    fn test_light_fit() {
+        let bounds = ModelBounds { x: (-55.0, -35.0), y: (40.0, 60.0), z: (0.0, 20.0) };
+        let direction = key_light_direction();
+        let matrix = light_matrix(&bounds, &direction);
+        let inside = |p: Vec3| {
+            let clip = matrix * Vec4::new(p.x, p.y, p.z, 1.0);
+            clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0 && (0.0..=1.0).contains(&clip.z)
+        };
+
+        for corner in 0..8 {
+            let x = if corner & 1 == 0 { bounds.x.0 } else { bounds.x.1 };
+            let y = if corner & 2 == 0 { bounds.y.0 } else { bounds.y.1 };
+            let z = if corner & 4 == 0 { bounds.z.0 } else { bounds.z.1 };
+            let p = Vec3::new(x, y, z);
+            assert!(inside(p), "{:?}", p);
+            //where the corner's shadow lands on the ground
+            assert!(inside(p - direction * (z - bounds.z.0) / direction.z));
+        }
+
+        //the side facing the light is nearer to it
+        let top = matrix * Vec4::new(-45.0, 50.0, 20.0, 1.0);
+        let bottom = matrix * Vec4::new(-45.0, 50.0, 0.0, 1.0);
+        assert!(top.z < bottom.z);
+        assert!(!inside(Vec3::new(-45.0, 50.0, 100.0)));
+    }
+}
@@ -0,0 +1,185 @@
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use ahash::AHashMap;
+use crate::loader::{ModelBounds, ModelData, Vertex};
+use crate::writer;
+
+/// Disjoint-set forest with path halving and union by size
+pub struct UnionFind {
+    parent: Vec<u32>,
+    size: Vec<u32>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        Self { parent: (0..n as u32).collect(), size: vec![1; n] }
+    }
+
+    pub fn find(&mut self, mut x: u32) -> u32 {
+        while self.parent[x as usize] != x {
+            let grandparent = self.parent[self.parent[x as usize] as usize];
+            self.parent[x as usize] = grandparent;
+            x = grandparent;
+        }
+        x
+    }
+
+    pub fn union(&mut self, a: u32, b: u32) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a as usize] < self.size[b as usize] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b as usize] = a;
+        self.size[a as usize] += self.size[b as usize];
+    }
+}
+
+/// A connected set of triangles. After [`find_shells`], each shell occupies a contiguous range of the index buffer.
+#[derive(Debug, Clone)]
+pub struct Shell {
+    /// Range into `ModelData::indices` (not triangles)
+    pub indices: Range<u32>,
+    pub bounds: ModelBounds,
+}
+
+impl Shell {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn triangles<'a>(&self, data: &'a ModelData) -> std::slice::ChunksExact<'a, u32> {
+        data.indices[self.indices.start as usize..self.indices.end as usize].chunks_exact(3)
+    }
+
+    /// Writes this shell on its own as a binary STL file
+    pub fn export<P: AsRef<Path>>(&self, data: &ModelData, path: P) -> io::Result<()> {
+        writer::write_triangles(path, &data.vertices, self.triangles(data))
+    }
+
+    /// One line description used by the shell list
+    pub fn describe(&self) -> String {
+        let b = &self.bounds;
+        format!("{:>9} tris  {:.2} x {:.2} x {:.2}", self.triangle_count(), b.x.1 - b.x.0, b.y.1 - b.y.0, b.z.1 - b.z.0)
+    }
+}
+
+/// File name for an exported shell, placed next to the original: `dir/part.stl` -> `dir/part_shell3.stl`
+pub fn export_path<P: AsRef<Path>>(input: P, index: usize) -> PathBuf {
+    let input = input.as_ref();
+    let stem = input.file_stem().map(|s| s.to_string_lossy()).unwrap_or_else(|| "model".into());
+    input.with_file_name(format!("{}_shell{}.stl", stem, index))
+}
+
+/// Splits the model into connected components, largest first. Triangles are reordered in place so
+/// that every shell can be drawn with a single indexed draw call. Vertices at the same position are
+/// treated as connected, since the loader workers don't deduplicate across their boundaries.
+///
+/// Returns the shells and the shell each vertex belongs to.
+pub fn find_shells(data: &mut ModelData) -> (Vec<Shell>, Vec<u32>) {
+    let mut sets = UnionFind::new(data.vertices.len());
+
+    let mut positions: AHashMap<Vertex, u32> = AHashMap::with_capacity(data.vertices.len());
+    for (i, vertex) in data.vertices.iter().enumerate() {
+        let first = *positions.entry(*vertex).or_insert(i as u32);
+        sets.union(first, i as u32);
+    }
+    drop(positions);
+
+    for tri in data.indices.chunks_exact(3) {
+        sets.union(tri[0], tri[1]);
+        sets.union(tri[1], tri[2]);
+    }
+
+    //number the roots in order of decreasing triangle count
+    let mut counts: AHashMap<u32, usize> = AHashMap::new();
+    for tri in data.indices.chunks_exact(3) {
+        *counts.entry(sets.find(tri[0])).or_default() += 1;
+    }
+    let mut roots: Vec<(u32, usize)> = counts.into_iter().collect();
+    roots.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let shell_of_root: AHashMap<u32, u32> = roots.iter().enumerate().map(|(i, (root, _))| (*root, i as u32)).collect();
+
+    let mut triangles: Vec<[u32; 3]> = data.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    triangles.sort_by_cached_key(|tri| shell_of_root[&sets.find(tri[0])]);
+
+    let vertex_shells: Vec<u32> = (0..data.vertices.len() as u32)
+        .map(|v| shell_of_root.get(&sets.find(v)).copied().unwrap_or(u32::MAX))
+        .collect();
+
+    let mut shells = Vec::with_capacity(roots.len());
+    let mut start = 0;
+    for (_, count) in roots {
+        let end = start + count;
+        let mut bounds = ModelBounds::default();
+        for tri in &triangles[start..end] {
+            for idx in tri {
+                bounds.update(&data.vertices[*idx as usize]);
+            }
+        }
+        shells.push(Shell { indices: (start * 3) as u32..(end * 3) as u32, bounds });
+        start = end;
+    }
+
+    data.indices = triangles.into_iter().flatten().collect();
+    (shells, vertex_shells)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use crate::loader::{Loader, ModelData, Vertex};
+    use super::find_shells;
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    #[test]
+    fn test_single_shell() {
+        let mut data = cube();
+        let (shells, vertex_shells) = find_shells(&mut data);
+        assert_eq!(shells.len(), 1);
+        assert_eq!(shells[0].triangle_count(), 12);
+        assert_eq!(shells[0].bounds.x, (-55.0, -35.0));
+        assert!(vertex_shells.iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn test_two_shells() {
+        let mut data = cube();
+        //append a second cube 100 units along x, minus one triangle so the shells have different sizes
+        let offset = data.vertices.len() as u32;
+        let moved: Vec<Vertex> = data.vertices.iter()
+            .map(|v| Vertex { pos: [v.pos[0] + 100.0, v.pos[1], v.pos[2]] })
+            .collect();
+        data.vertices.extend(moved);
+        let moved_indices: Vec<u32> = data.indices[3..].iter().map(|i| i + offset).collect();
+        data.indices.splice(0..0, moved_indices);
+
+        let (shells, vertex_shells) = find_shells(&mut data);
+        assert_eq!(shells.len(), 2);
+        assert_eq!(shells[0].triangle_count(), 12);
+        assert_eq!(shells[1].triangle_count(), 11);
+        assert_eq!(shells[0].bounds.x, (-55.0, -35.0));
+        assert_eq!(shells[1].bounds.x, (45.0, 65.0));
+        assert_eq!(shells[1].indices, 36..69);
+        assert!(shells[1].triangles(&data).flatten().all(|i| vertex_shells[*i as usize] == 1));
+    }
+
+    #[test]
+    fn test_duplicate_positions_connected() {
+        let mut data = cube();
+        //point half of the cube at copies of its vertices, like a seam between two loader workers
+        let offset = data.vertices.len() as u32;
+        data.vertices.extend(data.vertices.clone());
+        for idx in data.indices.iter_mut().skip(18) {
+            *idx += offset;
+        }
+        let (shells, _) = find_shells(&mut data);
+        assert_eq!(shells.len(), 1);
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use ahash::{AHashMap, AHashSet};
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3};
+use crate::loader::{ModelBounds, ModelData};
+use crate::repair::{weld_map, weld_tolerance};
+use crate::section::SectionPlane;
+use crate::units::Units;
+
+/// Polyline where a plane cuts the mesh, in the plane's 2D [`basis`](SectionPlane::basis).
+/// Closed outer boundaries wind counter-clockwise and holes clockwise, for a mesh facing outwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Vec2>,
+    /// False if the chain of segments ran into a hole in the mesh
+    pub closed: bool,
+}
+
+impl Contour {
+    /// Signed area enclosed by the contour, positive when counter-clockwise. Open contours have none.
+    pub fn area(&self) -> f64 {
+        if !self.closed {
+            return 0.0;
+        }
+        let n = self.points.len();
+        (0..n).map(|i| {
+            let (a, b) = (self.points[i], self.points[(i + 1) % n]);
+            a.x as f64 * b.y as f64 - b.x as f64 * a.y as f64
+        }).sum::<f64>() / 2.0
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        let segments = self.points.windows(2).map(|w| (w[1] - w[0]).magnitude() as f64).sum::<f64>();
+        match (self.closed, self.points.first(), self.points.last()) {
+            (true, Some(first), Some(last)) => segments + (first - last).magnitude() as f64,
+            _ => segments,
+        }
+    }
+}
+
+/// Every contour in one plane
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub plane: SectionPlane,
+    pub contours: Vec<Contour>,
+    pub units: Units,
+}
+
+impl Layer {
+    /// Cross-sectional area of the solid, with holes subtracted
+    pub fn area(&self) -> f64 {
+        self.contours.iter().map(Contour::area).sum()
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        self.contours.iter().map(Contour::perimeter).sum()
+    }
+
+    pub fn open_contours(&self) -> usize {
+        self.contours.iter().filter(|c| !c.closed).count()
+    }
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} contour(s), area {:.3} {u}², perimeter {:.3} {u}",
+            self.plane.position(), self.contours.len(), self.area(), self.perimeter(), u = self.units)?;
+        match self.open_contours() {
+            0 => Ok(()),
+            n => write!(f, " ({} open)", n),
+        }
+    }
+}
+
+/// File name for an exported section, placed next to the original: `dir/part.stl` -> `dir/part_section.svg`
+pub fn export_path<P: AsRef<Path>>(input: P, extension: &str) -> PathBuf {
+    let input = input.as_ref();
+    let stem = input.file_stem().map(|s| s.to_string_lossy()).unwrap_or_else(|| "model".into());
+    input.with_file_name(format!("{}_section.{}", stem, extension))
+}
+
+/// Parses an axis name for the command line: x, y or z
+pub fn parse_axis(s: &str) -> Result<usize, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "x" => Ok(0),
+        "y" => Ok(1),
+        "z" => Ok(2),
+        _ => Err(format!("Unknown axis '{}', expected x, y or z", s)),
+    }
+}
+
+/// Mesh edge between two welded vertices, lower id first. Both triangles sharing an edge produce the
+/// same key and the same crossing point, which is what lets segments be chained exactly.
+type EdgeKey = (u32, u32);
+
+struct Segment {
+    start: EdgeKey,
+    end: EdgeKey,
+    points: [Vec2; 2],
+}
+
+/// Mesh prepared for slicing: welded triangle corners and their positions
+struct Slicer<'a> {
+    data: &'a ModelData,
+    corners: Vec<[u32; 3]>,
+}
+
+impl<'a> Slicer<'a> {
+    fn new(data: &'a ModelData) -> Self {
+        let welded = weld_map(&data.vertices, weld_tolerance(&ModelBounds::from_vertices(&data.vertices)));
+        let corners = data.indices.chunks_exact(3)
+            .map(|t| [welded[t[0] as usize], welded[t[1] as usize], welded[t[2] as usize]])
+            .collect();
+        Self { data, corners }
+    }
+
+    fn position(&self, v: u32) -> Vec3 {
+        Vec3::from(self.data.vertices[v as usize].pos)
+    }
+
+    /// Segment where the plane crosses a triangle. Vertices exactly on the plane count as above it, which
+    /// avoids zero-length segments and keeps every crossing on an edge.
+    fn segment(&self, tri: &[u32; 3], plane: &SectionPlane, (u, v): (Vec3, Vec3)) -> Option<Segment> {
+        let p = tri.map(|i| self.position(i));
+        let above = p.map(|p| plane.normal.dot(&p) >= plane.offset);
+        let crossings: Vec<usize> = (0..3).filter(|i| above[*i] != above[(*i + 1) % 3]).collect();
+        if crossings.len() != 2 {
+            return None;
+        }
+
+        let crossing = |edge: usize| {
+            let (a, b) = (tri[edge], tri[(edge + 1) % 3]);
+            let (lo, hi) = (a.min(b), a.max(b));
+            let (pl, ph) = (self.position(lo), self.position(hi));
+            let (dl, dh) = (plane.normal.dot(&pl) - plane.offset, plane.normal.dot(&ph) - plane.offset);
+            let point = pl + (ph - pl) * (dl / (dl - dh));
+            ((lo, hi), Vec2::new(point.dot(&u), point.dot(&v)))
+        };
+        let (k0, p0) = crossing(crossings[0]);
+        let (k1, p1) = crossing(crossings[1]);
+
+        //run along normal x face normal, which leaves the solid on the left
+        let face_normal = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        let direction = plane.normal.cross(&face_normal);
+        let along = direction.dot(&u) * (p1.x - p0.x) + direction.dot(&v) * (p1.y - p0.y);
+        Some(if along >= 0.0 {
+            Segment { start: k0, end: k1, points: [p0, p1] }
+        } else {
+            Segment { start: k1, end: k0, points: [p1, p0] }
+        })
+    }
+}
+
+/// Joins segments end to start into polylines. Chains with a loose end are traced from their first segment,
+/// and whatever is left forms closed loops. Where the mesh is non-manifold, the last segment wins.
+fn chain(segments: Vec<Segment>) -> Vec<Contour> {
+    let by_start: AHashMap<EdgeKey, usize> = segments.iter().enumerate().map(|(i, s)| (s.start, i)).collect();
+    let ends: AHashSet<EdgeKey> = segments.iter().map(|s| s.end).collect();
+    let mut used = vec![false; segments.len()];
+    let mut contours = Vec::new();
+
+    let open_starts: Vec<usize> = (0..segments.len()).filter(|i| !ends.contains(&segments[*i].start)).collect();
+    for first in open_starts.into_iter().chain(0..segments.len()) {
+        if used[first] {
+            continue;
+        }
+        let mut points = vec![segments[first].points[0]];
+        let mut current = first;
+        let closed = loop {
+            used[current] = true;
+            points.push(segments[current].points[1]);
+            match by_start.get(&segments[current].end) {
+                Some(next) if *next == first => break true,
+                Some(next) if !used[*next] => current = *next,
+                _ => break false,
+            }
+        };
+        if closed {
+            //the last point is the first one again
+            points.pop();
+        }
+        contours.push(Contour { points, closed });
+    }
+    contours
+}
+
+/// Intersects the mesh with a single plane
+pub fn slice(data: &ModelData, plane: &SectionPlane) -> Layer {
+    let slicer = Slicer::new(data);
+    let basis = plane.basis();
+    let segments = slicer.corners.iter().filter_map(|tri| slicer.segment(tri, plane, basis)).collect();
+    Layer { plane: *plane, contours: chain(segments), units: data.units }
+}
+
+/// Slices the mesh into layers of the given height along an axis, with each plane in the middle of
+/// its layer like a slicer would. Each triangle is only intersected with the planes it spans.
+pub fn slice_layers(data: &ModelData, axis: usize, layer_height: f32) -> Vec<Layer> {
+    let bounds = &data.bounds;
+    let (min, max) = [bounds.x, bounds.y, bounds.z][axis];
+    if layer_height <= 0.0 || max < min {
+        return Vec::new();
+    }
+    let count = (((max - min) / layer_height).ceil() as usize).max(1);
+    let base = SectionPlane::axis(axis, bounds);
+    let planes: Vec<SectionPlane> = (0..count)
+        .map(|i| base.with_offset(min + (i as f32 + 0.5) * layer_height))
+        .collect();
+
+    let slicer = Slicer::new(data);
+    let basis = base.basis();
+    let mut segments: Vec<Vec<Segment>> = planes.iter().map(|_| Vec::new()).collect();
+    for tri in &slicer.corners {
+        let heights = tri.map(|v| slicer.position(v)[axis]);
+        let lo = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let hi = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let first = ((lo - min) / layer_height - 0.5).ceil().max(0.0) as usize;
+        let last = (((hi - min) / layer_height - 0.5).floor().max(-1.0) as i64).min(count as i64 - 1);
+        for layer in first as i64..=last {
+            if let Some(segment) = slicer.segment(tri, &planes[layer as usize], basis) {
+                segments[layer as usize].push(segment);
+            }
+        }
+    }
+
+    planes.into_iter().zip(segments)
+        .map(|(plane, segments)| Layer { plane, contours: chain(segments), units: data.units })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use crate::loader::{Loader, ModelData};
+    use crate::section::SectionPlane;
+    use super::{slice, slice_layers};
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    #[test]
+    fn test_slice_cube() {
+        let data = cube();
+        for axis in 0..3 {
+            let mut plane = SectionPlane::axis(axis, &data.bounds);
+            let layer = slice(&data, &plane);
+            assert_eq!(layer.contours.len(), 1);
+            assert!(layer.contours[0].closed);
+            assert!((layer.area() - 400.0).abs() < 1e-3, "{}", layer.area());
+            assert!((layer.perimeter() - 80.0).abs() < 1e-3);
+
+            //the other side sees the same section in a mirrored basis, so the winding is preserved
+            plane.flip();
+            assert!((slice(&data, &plane).area() - 400.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_slice_through_vertices() {
+        //the bottom face lies exactly in the plane and counts as above it
+        let data = cube();
+        let plane = SectionPlane::axis(2, &data.bounds).with_offset(data.bounds.z.0);
+        assert!(slice(&data, &plane).contours.is_empty());
+    }
+
+    #[test]
+    fn test_layers() {
+        let data = cube();
+        let layers = slice_layers(&data, 2, 3.0);
+        //20 units in layers of 3 leaves a partial layer at the top, still sliced through its middle
+        assert_eq!(layers.len(), 7);
+        assert_eq!(layers[0].plane.offset, 1.5);
+        assert_eq!(layers[6].plane.offset, 19.5);
+        assert!(layers.iter().all(|l| l.contours.len() == 1 && (l.area() - 400.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_open_contour() {
+        let mut data = cube();
+        //removing the two triangles of one side leaves a gap in every section through it
+        let side: Vec<usize> = (0..12).filter(|t| {
+            (0..3).all(|i| data.vertices[data.indices[t * 3 + i] as usize].pos[0] == data.bounds.x.0)
+        }).collect();
+        assert_eq!(side.len(), 2);
+        for t in side.iter().rev() {
+            data.indices.drain(t * 3..t * 3 + 3);
+        }
+        let layer = slice(&data, &SectionPlane::axis(2, &data.bounds));
+        assert_eq!(layer.contours.len(), 1);
+        assert_eq!(layer.open_contours(), 1);
+        assert!((layer.perimeter() - 60.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::camera::Camera;
+use crate::model;
+
+/// Radius of the occlusion hemisphere in pixels, unless asked for otherwise
+pub const DEFAULT_RADIUS: f32 = 24.0;
+/// How dark fully occluded surfaces get, from 0 (not at all) to 1 (black)
+pub const DEFAULT_STRENGTH: f32 = 1.0;
+
+const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Mirrors `AoSettings` in ssao.wgsl, padded to 16 bytes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AoUniform {
+    to_clip: [f32; 16],
+    to_model: [f32; 16],
+    radius: f32,
+    strength: f32,
+    depth_scale: f32,
+    _padding: f32,
+}
+
+/// Screen-space ambient occlusion, computed from the depth buffer after the model is drawn and
+/// multiplied into the frame. Like the outline, it uses the first depth sample of each pixel with MSAA.
+pub struct Ssao {
+    occlusion_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    occlusion_bind_group_layout: wgpu::BindGroupLayout,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    /// Bind groups and the occlusion texture, which depend on the window size
+    targets: (wgpu::BindGroup, wgpu::BindGroup, wgpu::TextureView),
+    uniform_buffer: wgpu::Buffer,
+    /// Hemisphere radius in pixels
+    pub radius: f32,
+    pub strength: f32,
+}
+
+impl Ssao {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, depth: &wgpu::Texture) -> Self {
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("SSAO shader"),
+                source: wgpu::ShaderSource::Wgsl(model::depth_reading_shader(include_str!("ssao.wgsl"), sample_count)),
+            }
+        );
+
+        let uniform_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("SSAO settings buffer"),
+                size: std::mem::size_of::<AoUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+
+        let depth_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: sample_count > 1,
+            },
+            count: None
+        };
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None
+        };
+        let occlusion_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[depth_entry, uniform_entry],
+                label: Some("SSAO occlusion bind group layout"),
+            }
+        );
+        let composite_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    depth_entry,
+                    uniform_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("SSAO composite bind group layout"),
+            }
+        );
+
+        let occlusion_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO occlusion pipeline layout"),
+                bind_group_layouts: &[
+                    &occlusion_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let composite_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO composite pipeline layout"),
+                bind_group_layouts: &[
+                    &composite_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let occlusion_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("SSAO occlusion pipeline"),
+                layout: Some(&occlusion_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_occlusion",
+                    targets: &[wgpu::ColorTargetState {
+                        format: OCCLUSION_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        //multiplies the frame by the shader output, leaving alpha alone
+        let composite_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("SSAO composite pipeline"),
+                layout: Some(&composite_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_composite",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::Src,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        let targets = Self::targets(device, config, &occlusion_bind_group_layout, &composite_bind_group_layout, &uniform_buffer, depth);
+
+        Self {
+            occlusion_pipeline,
+            composite_pipeline,
+            occlusion_bind_group_layout,
+            composite_bind_group_layout,
+            targets,
+            uniform_buffer,
+            radius: DEFAULT_RADIUS,
+            strength: DEFAULT_STRENGTH,
+        }
+    }
+
+    /// Creates the occlusion texture at the size of the surface, and the bind groups reading from it
+    fn targets(device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        occlusion_layout: &wgpu::BindGroupLayout,
+        composite_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        depth: &wgpu::Texture) -> (wgpu::BindGroup, wgpu::BindGroup, wgpu::TextureView) {
+        let occlusion = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("SSAO occlusion texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OCCLUSION_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let occlusion_view = occlusion.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = model::depth_reading_view(depth);
+
+        let occlusion_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: occlusion_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("SSAO occlusion bind group"),
+            }
+        );
+        let composite_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: composite_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&occlusion_view),
+                    },
+                ],
+                label: Some("SSAO composite bind group"),
+            }
+        );
+        (occlusion_bind_group, composite_bind_group, occlusion_view)
+    }
+
+    /// Must be called whenever the depth texture is recreated, e.g. after a resize
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, depth: &wgpu::Texture) {
+        self.targets = Self::targets(device, config,
+            &self.occlusion_bind_group_layout, &self.composite_bind_group_layout, &self.uniform_buffer, depth);
+    }
+
+    pub fn draw(&self, camera: &Camera, frame: &wgpu::SurfaceTexture, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+        let mut to_clip = [0.0; 16];
+        to_clip.copy_from_slice(camera.mat().as_slice());
+        let mut to_model = [0.0; 16];
+        to_model.copy_from_slice(camera.mat_i().as_slice());
+        let uniform = AoUniform {
+            to_clip,
+            to_model,
+            radius: self.radius * camera.pixel_size(),
+            strength: self.strength,
+            depth_scale: camera.depth_scale(),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let (occlusion_bind_group, composite_bind_group, occlusion_view) = &self.targets;
+        {
+            let mut render_pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("SSAO occlusion pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: occlusion_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        }
+                    }],
+                    depth_stencil_attachment: None,
+                }
+            );
+            render_pass.set_pipeline(&self.occlusion_pipeline);
+            render_pass.set_bind_group(0, occlusion_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("SSAO composite pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: None,
+            }
+        );
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
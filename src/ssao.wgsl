@@ -0,0 +1,122 @@
+//Screen-space ambient occlusion. The first pass rebuilds each pixel's position and normal from the depth
+//buffer and counts how many points in a hemisphere above the surface are hidden behind other geometry.
+//The second pass blurs the result over the 4x4 tile of sample rotations and darkens the frame with it.
+
+struct AoSettings {
+    //model to clip space and back
+    to_clip: mat4x4<f32>;
+    to_model: mat4x4<f32>;
+    //hemisphere radius in model units
+    radius: f32;
+    strength: f32;
+    //change in depth per model unit along the view direction
+    depth_scale: f32;
+};
+
+[[group(0), binding(0)]]
+var depth: texture_depth_2d;
+[[group(0), binding(1)]]
+var<uniform> ao: AoSettings;
+
+//a single triangle covering the screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let x = f32(index & 1u) * 4.0 - 1.0;
+    let y = f32(index >> 1u) * 4.0 - 1.0;
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+fn depth_at(pixel: vec2<i32>) -> f32 {
+    let size = textureDimensions(depth);
+    return textureLoad(depth, clamp(pixel, vec2<i32>(0, 0), size - vec2<i32>(1, 1)), 0);
+}
+
+fn to_ndc(pixel: vec2<i32>) -> vec2<f32> {
+    let size = vec2<f32>(textureDimensions(depth));
+    let uv = (vec2<f32>(pixel) + vec2<f32>(0.5, 0.5)) / size;
+    return vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+fn position_at(pixel: vec2<i32>) -> vec3<f32> {
+    let p = ao.to_model * vec4<f32>(to_ndc(pixel), depth_at(pixel), 1.0);
+    return p.xyz / p.w;
+}
+
+//difference to whichever neighbour is on the same surface, judging by the smaller change in depth
+fn surface_step(pixel: vec2<i32>, step: vec2<i32>, center: vec3<f32>, d: f32) -> vec3<f32> {
+    if (abs(depth_at(pixel + step) - d) < abs(depth_at(pixel - step) - d)) {
+        return position_at(pixel + step) - center;
+    }
+    return center - position_at(pixel - step);
+}
+
+[[stage(fragment)]]
+fn fs_occlusion([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let d = depth_at(pixel);
+    //the background is cleared to 0
+    if (d <= 0.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    let p = position_at(pixel);
+    var n = normalize(cross(surface_step(pixel, vec2<i32>(1, 0), p, d), surface_step(pixel, vec2<i32>(0, 1), p, d)));
+    //towards the near plane along the pixel's own ray, which converges on the camera in perspective
+    let near = ao.to_model * vec4<f32>(to_ndc(pixel), 1.0, 1.0);
+    let towards_camera = near.xyz / near.w - p;
+    if (dot(n, towards_camera) < 0.0) {
+        n = -n;
+    }
+
+    //rotate the sample pattern differently for every pixel of a 4x4 tile
+    let tile = (pixel.x & 3) + (pixel.y & 3) * 4;
+    let rotation = f32(tile) * 0.3926991;
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(n.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let t = normalize(cross(n, helper));
+    let b = cross(n, t);
+
+    let size = vec2<f32>(textureDimensions(depth));
+    var occlusion = 0.0;
+    for (var i = 0; i < 16; i = i + 1) {
+        //spiral through the hemisphere, with more samples close to the surface point
+        let f = (f32(i) + 0.5) / 16.0;
+        let angle = f32(i) * 2.3999632 + rotation;
+        let direction = (t * cos(angle) + b * sin(angle)) * sqrt(f) + n * sqrt(1.0 - f);
+        let s = p + direction * ao.radius * mix(0.1, 1.0, f * f);
+
+        let clip = ao.to_clip * vec4<f32>(s, 1.0);
+        let ndc = clip.xyz / clip.w;
+        let sample_pixel = vec2<i32>(vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * size);
+        //distance by which the surface at that pixel is in front of the sample point
+        let in_front = (depth_at(sample_pixel) - ndc.z) / ao.depth_scale;
+        if (in_front > ao.radius * 0.02) {
+            //surfaces much further in front don't occlude, they are just in the way
+            occlusion = occlusion + smoothStep(0.0, 1.0, ao.radius * ao.depth_scale / abs(depth_at(sample_pixel) - d));
+        }
+    }
+    return vec4<f32>(occlusion / 16.0, 0.0, 0.0, 0.0);
+}
+
+[[group(0), binding(2)]]
+var occlusion_texture: texture_2d<f32>;
+
+//averages the 4x4 tile and multiplies the frame by the result, see the blend state in ssao.rs
+[[stage(fragment)]]
+fn fs_composite([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    if (depth_at(pixel) <= 0.0) {
+        discard;
+    }
+    let size = textureDimensions(occlusion_texture);
+    var total = 0.0;
+    for (var y = -2; y < 2; y = y + 1) {
+        for (var x = -2; x < 2; x = x + 1) {
+            let p = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+            total = total + textureLoad(occlusion_texture, p, 0).r;
+        }
+    }
+    let light = 1.0 - clamp(total / 16.0 * ao.strength, 0.0, 1.0);
+    return vec4<f32>(light, light, light, 1.0);
+}
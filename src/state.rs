@@ -1,13 +1,98 @@
 
-use nalgebra_glm::Vec2;
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3};
 use winit::dpi::PhysicalSize;
-use winit::event::{DeviceEvent, MouseScrollDelta, WindowEvent, ElementState};
+use winit::event::{DeviceEvent, MouseButton, MouseScrollDelta, WindowEvent, ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
 
-use crate::camera::Camera;
-use crate::loader::ModelBounds;
-use crate::model::Model;
+use crate::camera::{self, Camera};
+use crate::loader::ModelData;
+use crate::model::{Model, RenderTarget, ShadingTextures};
 use crate::bg::Background;
 use crate::color::Theme;
+use crate::info::ModelInfo;
+use crate::overlay::{Anchor, Overlay};
+use crate::annotation::Annotations;
+use crate::outline::Outline;
+use crate::ssao::Ssao;
+use crate::xray::XRay;
+use crate::grid::Grid;
+use crate::viewcube::{self, ViewCube};
+use crate::environment::{Environment, EnvironmentMap};
+use crate::matcap::{Image, Matcap, MatcapTexture};
+use crate::edges::{self, DEFAULT_FEATURE_ANGLE};
+use crate::color::Color;
+use crate::measure::{Measurement, Tool};
+use crate::picking::{Pick, Picker};
+use crate::model::VertexAttributes;
+use crate::settings::{GridMode, Material, Printer, RenderSettings, Shading};
+use crate::shells::{self, Shell};
+use crate::intersect;
+use crate::overhang;
+use crate::thickness::{self, ThicknessReport};
+use crate::curvature::{self, Curvature};
+use crate::section::SectionPlane;
+use crate::slice;
+use crate::writer;
+
+/// Maximum number of shells listed on screen at once
+const SHELL_LIST_LENGTH: usize = 20;
+
+/// Degrees the overhang threshold changes by per key press
+const OVERHANG_STEP: f32 = 5.0;
+
+/// Millimetres the minimum wall thickness changes by per key press
+const THICKNESS_STEP: f32 = 0.1;
+
+/// Factor the curvature colour map range is scaled by per key press
+const CURVATURE_STEP: f32 = 1.25;
+
+/// Degrees the arrow keys turn the view by
+const ORBIT_STEP: f32 = 15.0;
+/// Fraction of the window the arrow keys pan by, with Shift held
+const PAN_STEP: f32 = 0.1;
+/// Factor the zoom keys scale the view by
+const ZOOM_STEP: f32 = 1.25;
+
+/// Mouse movement between press and release, in pixels, below which a press counts as a click
+const CLICK_TOLERANCE: f32 = 4.0;
+
+/// Colour of the marker on the point the view is rotating about
+const PIVOT_COLOR: Color = [0.2, 0.6, 1.0, 1.0];
+/// Colour of points picked for a measurement that isn't complete yet
+const PENDING_COLOR: Color = [1.0, 0.3, 0.02, 1.0];
+
+/// Length of the axis triad arms, and the distance of its origin from the corner of the window, in pixels
+const TRIAD_SIZE: f32 = 40.0;
+/// Colours of the X, Y and Z arms of the axis triad
+const TRIAD_COLORS: [Color; 3] = [[0.9, 0.15, 0.15, 1.0], [0.2, 0.75, 0.2, 1.0], [0.25, 0.45, 1.0, 1.0]];
+
+/// User-configurable settings passed in from the command line
+pub struct Options {
+    pub filename: String,
+    pub theme: Theme,
+    /// Material density in g/cm³, used for mass properties
+    pub density: f64,
+    /// Always rotate about the middle of the view, rather than the surface point under the cursor
+    pub orbit_center: bool,
+    /// MSAA samples per pixel, 1 for no anti-aliasing
+    pub sample_count: u32,
+    /// Ambient occlusion radius in pixels
+    pub ao_radius: f32,
+    /// How dark ambient occlusion makes creases, from 0 to 1
+    pub ao_strength: f32,
+    /// Radiance HDR file lighting physically based materials, instead of the built-in studio
+    pub environment: Option<String>,
+    /// Material to start out with, instead of the theme colours
+    pub material: Option<Material>,
+    /// PNG matcaps to offer after the built-in ones. The first is selected at startup.
+    pub matcaps: Vec<String>,
+    pub grid: GridMode,
+    /// Printer whose build volume is drawn at startup
+    pub printer: Option<Printer>,
+    pub perspective: bool,
+    /// Vertical field of view of the perspective projection, in degrees
+    pub field_of_view: f32,
+}
 
 pub struct State {
     pub start_time: std::time::SystemTime,
@@ -19,34 +104,149 @@ pub struct State {
     pub size: PhysicalSize<u32>,
     background: Background,
     depth: (wgpu::Texture, wgpu::TextureView, wgpu::Sampler),
+    /// Multisampled colour target that the background and model are drawn into, if MSAA is on
+    msaa: Option<(wgpu::Texture, wgpu::TextureView)>,
     is_first_frame: bool,
-    bounds: ModelBounds,
-    theme: Theme,
+    data: ModelData,
+    shells: Vec<Shell>,
+    hidden_shells: Vec<bool>,
+    /// Whether self-intersecting faces are currently highlighted
+    show_intersections: bool,
+    /// Per-vertex buffer contents, kept so that analyses can fill in their part
+    attributes: Vec<VertexAttributes>,
+    /// Wall thickness per vertex, measured the first time it is shown
+    thickness: Option<Vec<f32>>,
+    /// Mean and Gaussian curvature per vertex, estimated the first time it is shown
+    curvature: Option<Curvature>,
+    /// Keyboard modifiers currently held, used to move the section plane with the mouse wheel
+    modifiers: ModifiersState,
+    settings: RenderSettings,
+    info: ModelInfo,
+    show_info: bool,
+    /// Result of the last viewer action, shown in the bottom corner of the screen
+    status: Option<String>,
+    overlay: Overlay,
+    annotations: Annotations,
+    outline: Outline,
+    ssao: Ssao,
+    xray: XRay,
+    grid: Grid,
+    view_cube: ViewCube,
+    environment: EnvironmentMap,
+    /// Environment still to be copied to its textures, which needs the queue of the first frame
+    pending_environment: Option<Environment>,
+    /// Built-in matcaps followed by those given on the command line
+    matcaps: Vec<Matcap>,
+    matcap_texture: MatcapTexture,
+    /// Matcap image still to be copied to the texture, like `pending_environment`
+    pending_matcap: Option<Image>,
+    /// Finds the surface under the cursor, recreated whenever the model changes
+    picker: Option<Picker>,
+    measure_tool: Option<Tool>,
+    /// Points picked for the measurement in progress
+    picks: Vec<Pick>,
+    measurements: Vec<Measurement>,
+    /// Normalized cursor position when the left button went down, to tell clicks from drags
+    press_cursor: Option<Vec2>,
+    /// Whether dragging rotates about the surface point under the cursor
+    orbit_cursor: bool,
+    options: Options,
 }
 
 impl State {
-    pub fn new(start_time: std::time::SystemTime, model: Option<Model>, theme: Theme, bounds: ModelBounds, size: PhysicalSize<u32>, 
+    pub fn new(start_time: std::time::SystemTime, data: ModelData, options: Options, size: PhysicalSize<u32>, 
              surface: wgpu::Surface, device: wgpu::Device, config: wgpu::SurfaceConfiguration) -> Self {
     
         surface.configure(&device, &config);
         
-        let background = Background::new(&device, &config, &theme);
-        let depth = Model::get_depth_texture(&config, &device);
+        let background = Background::new(&device, &config, &options.theme, options.sample_count);
+        let overlay = Overlay::new(&device, &config, &options.theme);
+        let annotations = Annotations::new(&device, &config);
+        let depth = Model::get_depth_texture(&config, &device, options.sample_count);
+        let msaa = Model::get_msaa_texture(&config, &device, options.sample_count);
+        let outline = Outline::new(&device, &config, &options.theme, options.sample_count, &depth.0);
+        let mut ssao = Ssao::new(&device, &config, options.sample_count, &depth.0);
+        ssao.radius = options.ao_radius;
+        ssao.strength = options.ao_strength;
+        let xray = XRay::new(&device, &config, options.sample_count);
+        let grid = Grid::new(&device, &config, &options.theme, options.sample_count);
+        let view_cube = ViewCube::new(&device, &config, &options.theme);
+        let info = ModelInfo::new(&data, options.density);
+        let pending_environment = match &options.environment {
+            Some(path) => Environment::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not load environment {}: {}", path, e);
+                Environment::studio()
+            }),
+            None => Environment::studio(),
+        };
+        let environment = EnvironmentMap::new(&device, &pending_environment);
+        let matcap_texture = MatcapTexture::new(&device);
+        let settings = RenderSettings {
+            material: options.material,
+            grid: options.grid,
+            printer: options.printer,
+            ..Default::default()
+        };
+        let mut matcaps = Matcap::built_in();
+        let first_custom = matcaps.len();
+        matcaps.extend(options.matcaps.iter().cloned().map(Matcap::File));
         
-        Self { 
+        let mut state = Self { 
             start_time, 
             surface, 
             config,
             device,
             camera: Camera::new(size.width as f32, size.height as f32), 
-            model,
-            bounds,
+            model: None,
+            data,
+            shells: Vec::new(),
+            hidden_shells: Vec::new(),
+            show_intersections: false,
+            attributes: Vec::new(),
+            thickness: None,
+            curvature: None,
+            modifiers: ModifiersState::empty(),
+            settings,
+            info,
+            show_info: false,
+            status: None,
+            overlay,
+            annotations,
+            outline,
+            ssao,
+            xray,
+            grid,
+            view_cube,
+            environment,
+            pending_environment: Some(pending_environment),
+            matcaps,
+            matcap_texture,
+            pending_matcap: None,
+            picker: None,
+            measure_tool: None,
+            picks: Vec::new(),
+            measurements: Vec::new(),
+            press_cursor: None,
+            orbit_cursor: !options.orbit_center,
             size,
             depth,
+            msaa,
             background,
             is_first_frame: true,
-            theme            
+            options,
+        };
+        state.camera.set_field_of_view(state.options.field_of_view);
+        state.camera.set_up(state.data.up);
+        state.settings.build_direction = state.data.up;
+        state.settings.min_thickness = state.data.units.millimetres_to_units(state.settings.min_thickness);
+        if state.options.perspective {
+            state.camera.toggle_projection();
+        }
+        if !state.options.matcaps.is_empty() {
+            state.select_matcap(Some(first_custom));
         }
+        state.rebuild_model();
+        state
     }
 
     pub fn resize(&mut self, size: PhysicalSize<u32>){
@@ -54,29 +254,73 @@ impl State {
         self.config.width = size.width;
         self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
-        self.depth = Model::get_depth_texture(&self.config, &self.device);
+        self.depth = Model::get_depth_texture(&self.config, &self.device, self.options.sample_count);
+        self.msaa = Model::get_msaa_texture(&self.config, &self.device, self.options.sample_count);
+        self.outline.set_depth(&self.device, &self.depth.0);
+        self.ssao.resize(&self.device, &self.config, &self.depth.0);
+        self.xray.resize(&self.device, &self.config);
         self.camera.set_size(size.width as f32, size.height as f32);
+        self.annotations.set_size(size.width, size.height);
+        self.view_cube.set_size(size.width);
     }
 
     pub fn render(&mut self, queue: &wgpu::Queue) -> Result<(), wgpu::SurfaceError> {
+        if let Some(environment) = self.pending_environment.take() {
+            self.environment.upload(queue, &environment);
+        }
+        if let Some(image) = self.pending_matcap.take() {
+            self.matcap_texture.upload(queue, &image);
+        }
+        self.camera.update();
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{ label: None});
         let frame = self.surface.get_current_texture()?;
+        let frame_view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        //with MSAA, every pass that draws into the multisampled target resolves it into the frame
+        let target = match &self.msaa {
+            Some((_, view)) => RenderTarget { color: view, resolve: Some(&frame_view), depth: &self.depth.1 },
+            None => RenderTarget { color: &frame_view, resolve: None, depth: &self.depth.1 },
+        };
         
-        self.background.draw(&frame, &self.depth.1, &mut encoder)?;
+        self.background.draw(&target, &mut encoder)?;
         if let Some(model) = &self.model {
-            model.draw(&self.camera, &frame, &self.depth.1, &mut encoder, &queue);
+            model.draw(&self.camera, &self.settings, &target, &self.xray, &mut encoder, queue);
+            self.grid.draw(&self.camera, &self.settings, &self.data, &target, &mut encoder, queue);
+            if self.settings.ambient_occlusion {
+                self.ssao.draw(&self.camera, &frame, &mut encoder, queue);
+            }
+            if self.settings.silhouette {
+                self.outline.draw(&self.camera, &frame, &mut encoder, queue);
+            }
+        }
+        if self.show_info {
+            self.overlay.queue(Anchor::TopLeft, self.info.to_string());
+        } else if self.settings.shading == Shading::Shells || self.settings.selected_shell.is_some() {
+            self.overlay.queue(Anchor::TopLeft, self.shell_list());
+        }
+        if let Some(status) = &self.status {
+            self.overlay.queue(Anchor::BottomLeft, status.clone());
         }
+        self.queue_measurements();
+        self.queue_axis_triad();
+        self.view_cube.draw(&self.camera, &frame, &mut encoder, queue, self.size.height as f32);
+        for ((x, y), name) in self.view_cube.labels(&self.camera.orientation()) {
+            //centred on the face, for 16 pixel monospace text
+            self.overlay.queue(Anchor::At(x - name.len() as f32 * 4.8, y - 9.0), name.to_string());
+        }
+        self.annotations.draw(&self.device, &frame, &mut encoder);
+        self.overlay.draw(&self.device, &frame, &mut encoder, self.size.width, self.size.height);
 
         if self.model.is_some() && self.is_first_frame {
             let end = std::time::SystemTime::now();
-            self.camera.fit_verts(&self.bounds);
+            self.camera.fit_verts(&self.data.bounds);
             let dt = end.duration_since(self.start_time).expect("Negative startup time calculated?!");
-            println!("First render in {:?}", dt);
+            eprintln!("First render in {:?}", dt);
             self.is_first_frame = false;
         }
         
         queue.submit(std::iter::once(encoder.finish()));
         frame.present();
+        self.overlay.recall();
 
         if self.is_first_frame {
             self.is_first_frame = false;
@@ -86,10 +330,8 @@ impl State {
     }
 
     pub fn device_event(&mut self, e: &DeviceEvent) {
-        if let DeviceEvent::MouseWheel { delta } = e {
-            if let MouseScrollDelta::PixelDelta(p) = delta {
-                self.camera.mouse_scroll(p.y as f32);
-            }
+        if let DeviceEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(p) } = e {
+            self.camera.mouse_scroll(p.y as f32);
         }
     }
 
@@ -97,8 +339,23 @@ impl State {
         match e {
             WindowEvent::MouseInput { state, button, .. } => {
                 match state {
-                    ElementState::Pressed => self.camera.mouse_pressed(*button),
-                    ElementState::Released => self.camera.mouse_released(*button),
+                    ElementState::Pressed => {
+                        if *button == MouseButton::Left {
+                            self.press_cursor = self.camera.cursor();
+                        }
+                        self.camera.mouse_pressed(*button);
+                        if *button == MouseButton::Left && self.orbit_cursor {
+                            if let Some(pick) = self.pick() {
+                                self.camera.set_pivot(pick.point);
+                            }
+                        }
+                    },
+                    ElementState::Released => {
+                        self.camera.mouse_released(*button);
+                        if *button == MouseButton::Left && self.is_click() {
+                            self.click();
+                        }
+                    },
                 }
                 true
             },
@@ -108,13 +365,545 @@ impl State {
             },
             WindowEvent::MouseWheel { delta, .. } => {
                 if let MouseScrollDelta::LineDelta(_, verti) = delta {
-                    self.camera.mouse_scroll(verti*10.0);
+                    if self.modifiers.ctrl() && self.settings.section.is_some() {
+                        self.move_section(verti.signum());
+                    } else {
+                        self.camera.mouse_scroll(verti*10.0);
+                    }
                 }
                 true
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                false
+            },
+            WindowEvent::KeyboardInput { 
+                input: KeyboardInput { 
+                    state: ElementState::Pressed, 
+                    virtual_keycode: Some(key),
+                    ..
+                }, .. 
+            } => self.key_pressed(*key),
+            _ => false,
+        }
+
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::R => self.repair(),
+            VirtualKeyCode::I => self.show_info = !self.show_info,
+            VirtualKeyCode::C => {
+                self.settings.shading = if self.settings.shading == Shading::Shells { Shading::Theme } else { Shading::Shells };
+            },
+            VirtualKeyCode::RBracket => self.select_shell(1),
+            VirtualKeyCode::LBracket => self.select_shell(-1),
+            VirtualKeyCode::H => self.toggle_selected_shell(),
+            VirtualKeyCode::U => {
+                self.hidden_shells.iter_mut().for_each(|h| *h = false);
+                self.update_draw_ranges();
+            },
+            VirtualKeyCode::E => self.export_shells(),
+            VirtualKeyCode::X => self.toggle_intersections(),
+            VirtualKeyCode::O => {
+                self.settings.shading = if self.settings.shading == Shading::Overhang { Shading::Theme } else { Shading::Overhang };
+                self.update_overhangs();
+            },
+            VirtualKeyCode::B => {
+                self.settings.build_direction = self.settings.build_direction.next();
+                self.update_overhangs();
+            },
+            VirtualKeyCode::T => self.toggle_thickness(),
+            VirtualKeyCode::Q => self.cycle_curvature(),
+            VirtualKeyCode::L => {
+                self.settings.feature_edges = !self.settings.feature_edges;
+                self.update_feature_edges();
+            },
+            VirtualKeyCode::K => self.settings.silhouette = !self.settings.silhouette,
+            VirtualKeyCode::A => self.settings.ambient_occlusion = !self.settings.ambient_occlusion,
+            VirtualKeyCode::D => self.settings.shadows = !self.settings.shadows,
+            VirtualKeyCode::G => self.settings.ground_shadow = !self.settings.ground_shadow,
+            VirtualKeyCode::Y => self.settings.xray = !self.settings.xray,
+            VirtualKeyCode::Z if self.modifiers.shift() => {
+                self.settings.printer = Printer::next(self.settings.printer);
+                self.status = Some(match self.settings.printer {
+                    Some(printer) => {
+                        let [w, d, h] = printer.build_volume();
+                        format!("Build volume: {} ({} × {} × {} mm)", printer, w, d, h)
+                    },
+                    None => "Build volume: off".to_string(),
+                });
+            },
+            VirtualKeyCode::Z => {
+                self.settings.grid = self.settings.grid.next();
+                self.status = Some(format!("Grid: {}", self.settings.grid));
+            },
+            VirtualKeyCode::N => {
+                self.settings.material = Material::next(self.settings.material);
+                self.settings.matcap = None;
+                self.status = Some(format!("Material: {}", self.settings.material.map_or("Theme".to_string(), |m| m.to_string())));
+            },
+            VirtualKeyCode::W => {
+                self.settings.mode = self.settings.mode.next();
+                self.prepare_edges();
+                self.status = Some(format!("Render mode: {}", self.settings.mode));
+            },
+            VirtualKeyCode::J => {
+                let next = match self.settings.matcap {
+                    None => Some(0),
+                    Some(i) if i + 1 < self.matcaps.len() => Some(i + 1),
+                    Some(_) => None,
+                };
+                self.select_matcap(next);
+            },
+            VirtualKeyCode::P => self.cycle_section_axis(),
+            VirtualKeyCode::F => {
+                if let Some(section) = &mut self.settings.section {
+                    section.flip();
+                    self.status = Some(section.to_string());
+                }
+            },
+            VirtualKeyCode::S => self.export_section(),
+            VirtualKeyCode::M => {
+                self.measure_tool = match self.measure_tool {
+                    None => Some(Tool::Distance),
+                    Some(tool) => tool.next(),
+                };
+                self.picks.clear();
+                self.status = self.measure_tool.map(|t| t.hint().to_string());
+            },
+            VirtualKeyCode::V => {
+                self.orbit_cursor = !self.orbit_cursor;
+                self.status = Some(if self.orbit_cursor { "Rotating about the point under the cursor" } else { "Rotating about the view centre" }.to_string());
+            },
+            VirtualKeyCode::Back | VirtualKeyCode::Delete => {
+                self.measurements.clear();
+                self.picks.clear();
+            },
+            VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => self.standard_view(Vec3::new(0.0, -1.0, 0.0)),
+            VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => self.standard_view(Vec3::new(1.0, 0.0, 0.0)),
+            VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => self.standard_view(Vec3::new(0.0, 0.0, 1.0)),
+            VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => self.standard_view(Vec3::new(1.0, -1.0, 1.0)),
+            VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => {
+                let perspective = self.camera.toggle_projection();
+                self.status = Some(format!("Projection: {}", if perspective { "perspective" } else { "orthographic" }));
+            },
+            VirtualKeyCode::Home => self.camera.zoom_to_fit(&self.data.bounds),
+            VirtualKeyCode::Left if self.modifiers.shift() => self.camera.pan(-PAN_STEP, 0.0),
+            VirtualKeyCode::Right if self.modifiers.shift() => self.camera.pan(PAN_STEP, 0.0),
+            VirtualKeyCode::Up if self.modifiers.shift() => self.camera.pan(0.0, PAN_STEP),
+            VirtualKeyCode::Down if self.modifiers.shift() => self.camera.pan(0.0, -PAN_STEP),
+            VirtualKeyCode::Left => self.camera.orbit(-ORBIT_STEP.to_radians(), 0.0),
+            VirtualKeyCode::Right => self.camera.orbit(ORBIT_STEP.to_radians(), 0.0),
+            VirtualKeyCode::Up => self.camera.orbit(0.0, -ORBIT_STEP.to_radians()),
+            VirtualKeyCode::Down => self.camera.orbit(0.0, ORBIT_STEP.to_radians()),
+            VirtualKeyCode::Minus if self.modifiers.ctrl() => self.step_threshold(-1.0),
+            VirtualKeyCode::Equals if self.modifiers.ctrl() => self.step_threshold(1.0),
+            //= is + without shift, so the main row zooms on keyboards without a numpad
+            VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd | VirtualKeyCode::PageUp => self.camera.zoom(ZOOM_STEP),
+            VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract | VirtualKeyCode::PageDown => self.camera.zoom(1.0 / ZOOM_STEP),
+            VirtualKeyCode::Comma => self.move_section(-1.0),
+            VirtualKeyCode::Period => self.move_section(1.0),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Finds the sharp edges the first time they are shown for the current model
+    fn update_feature_edges(&mut self) {
+        let model = match &mut self.model {
+            Some(model) if self.settings.feature_edges => model,
+            _ => return,
+        };
+        if !model.has_feature_edges() {
+            let edges = edges::feature_edges(&self.data, DEFAULT_FEATURE_ANGLE);
+            self.status = Some(format!("{} feature edges sharper than {}°", edges.len(), DEFAULT_FEATURE_ANGLE));
+            model.set_feature_edges(&self.device, edges);
+        }
+    }
+
+    /// Makes sure the model can draw its edges if the render mode needs them
+    fn prepare_edges(&mut self) {
+        if let (true, Some(model)) = (self.settings.mode.has_edges(), &mut self.model) {
+            model.prepare_edges(&self.device, &self.data.vertices, &self.data.indices);
+        }
+    }
+
+    /// Runs self-intersection detection and highlights the offending faces, or clears the highlight
+    fn toggle_intersections(&mut self) {
+        let model = match &mut self.model {
+            Some(model) => model,
+            None => return,
+        };
+        if self.show_intersections {
+            model.set_highlight(&self.device, &[], &[]);
+            self.show_intersections = false;
+            self.status = None;
+            return;
+        }
+
+        let start = std::time::SystemTime::now();
+        let result = intersect::find_self_intersections(&self.data, None);
+        let dt = std::time::SystemTime::now().duration_since(start).unwrap_or_default();
+        model.set_highlight(&self.device, &result.faces, &self.data.indices);
+        self.show_intersections = true;
+        self.status = Some(format!("Self-intersections: {} pairs, {} faces ({:?})", result.pairs.len(), result.faces.len(), dt));
+    }
+
+    /// Raises or lowers the threshold of the analysis currently shown
+    fn step_threshold(&mut self, direction: f32) {
+        if self.settings.shading == Shading::Thickness {
+            let step = self.data.units.millimetres_to_units(THICKNESS_STEP);
+            self.settings.min_thickness = (self.settings.min_thickness + direction * step).max(step);
+            self.update_thickness();
+        } else if matches!(self.settings.shading, Shading::MeanCurvature | Shading::GaussianCurvature) {
+            self.settings.curvature_range *= CURVATURE_STEP.powf(direction);
+            self.update_curvature();
+        } else {
+            self.settings.overhang_threshold = (self.settings.overhang_threshold + direction * OVERHANG_STEP).clamp(0.0, 90.0);
+            self.update_overhangs();
+        }
+    }
+
+    /// Switches to the wall thickness colour map, measuring the thickness first if needed
+    fn toggle_thickness(&mut self) {
+        if self.settings.shading == Shading::Thickness {
+            self.settings.shading = Shading::Theme;
+            self.status = None;
+            return;
+        }
+        if self.thickness.is_none() {
+            let thickness = thickness::wall_thickness(&self.data, None);
+            for (attributes, t) in self.attributes.iter_mut().zip(&thickness) {
+                attributes.thickness = *t;
+            }
+            if let Some(model) = &mut self.model {
+                model.set_attributes(&self.device, &self.attributes);
+            }
+            self.thickness = Some(thickness);
+        }
+        self.settings.shading = Shading::Thickness;
+        self.update_thickness();
+    }
+
+    fn update_thickness(&mut self) {
+        if let Some(thickness) = &self.thickness {
+            self.status = Some(ThicknessReport::new(thickness, self.settings.min_thickness, self.data.units).to_string());
+        }
+    }
+
+    /// Cycles the curvature colour maps: mean, Gaussian, then off. The curvature is estimated
+    /// the first time, and the range starts out covering most vertices of each map.
+    fn cycle_curvature(&mut self) {
+        self.settings.shading = match self.settings.shading {
+            Shading::MeanCurvature => Shading::GaussianCurvature,
+            Shading::GaussianCurvature => {
+                self.settings.shading = Shading::Theme;
+                self.status = None;
+                return;
+            },
+            _ => Shading::MeanCurvature,
+        };
+        if self.curvature.is_none() {
+            let curvature = curvature::vertex_curvature(&self.data);
+            for (attributes, (h, k)) in self.attributes.iter_mut().zip(curvature.mean.iter().zip(&curvature.gaussian)) {
+                attributes.curvature = [*h, *k];
             }
+            if let Some(model) = &mut self.model {
+                model.set_attributes(&self.device, &self.attributes);
+            }
+            self.curvature = Some(curvature);
+        }
+        if let Some(curvature) = &self.curvature {
+            self.settings.curvature_range = match self.settings.shading {
+                Shading::MeanCurvature => curvature::default_range(&curvature.mean),
+                _ => curvature::default_range(&curvature.gaussian),
+            };
+        }
+        self.update_curvature();
+    }
+
+    fn update_curvature(&mut self) {
+        self.status = Some(match self.settings.shading {
+            Shading::MeanCurvature => format!("Mean curvature: ±{:.4} /{}", self.settings.curvature_range, self.data.units),
+            _ => format!("Gaussian curvature: ±{:.5} /{}²", self.settings.curvature_range, self.data.units),
+        });
+    }
+
+    /// Moves the build plate under the model for the current build direction and reports the overhang area
+    fn update_overhangs(&mut self) {
+        self.settings.build_plate = overhang::build_plate(&self.data, self.settings.build_direction);
+        self.settings.plate_tolerance = overhang::plate_tolerance(&self.data.bounds);
+        if self.settings.shading == Shading::Overhang {
+            let report = overhang::find_overhangs(&self.data, self.settings.build_direction, self.settings.overhang_threshold);
+            self.status = Some(report.to_string());
+        }
+    }
+
+    /// Shades the model with a matcap from the list, or goes back to the theme colours
+    fn select_matcap(&mut self, index: Option<usize>) {
+        self.settings.matcap = None;
+        let matcap = match index {
+            Some(i) => &self.matcaps[i],
+            None => {
+                self.status = Some("Matcap: off".to_string());
+                return;
+            },
+        };
+        match matcap.load() {
+            Ok(image) => {
+                self.pending_matcap = Some(image);
+                self.settings.matcap = index;
+                self.settings.material = None;
+                self.status = Some(format!("Matcap: {}", matcap.name()));
+            },
+            Err(e) => self.status = Some(format!("Could not load matcap {}: {}", matcap.name(), e)),
+        }
+    }
+
+    /// Turns the section plane on along X, then snaps it to Y and Z, then turns it off again
+    fn cycle_section_axis(&mut self) {
+        let next = match self.settings.section.and_then(|s| s.snapped_axis()) {
+            None if self.settings.section.is_none() => Some(0),
+            Some(axis) if axis < 2 => Some(axis + 1),
+            _ => None,
+        };
+        self.settings.section = next.map(|axis| SectionPlane::axis(axis, &self.data.bounds));
+        self.status = self.settings.section.map(|s| s.to_string());
+    }
+
+    /// Moves the section plane by a number of steps along its normal
+    fn move_section(&mut self, steps: f32) {
+        if let Some(section) = &mut self.settings.section {
+            section.translate(steps * section.step());
+            self.status = Some(section.to_string());
+        }
+    }
+
+    /// Slices the model at the section plane, reports the contours and saves them as SVG and DXF
+    fn export_section(&mut self) {
+        let plane = match self.settings.section {
+            Some(plane) => plane,
+            None => {
+                self.status = Some("Press P to place a section plane first".to_string());
+                return;
+            }
+        };
+        let layers = [slice::slice(&self.data, &plane)];
+        let svg = slice::export_path(&self.options.filename, "svg");
+        let dxf = slice::export_path(&self.options.filename, "dxf");
+        let written = writer::write_svg(&svg, &layers).and_then(|_| writer::write_dxf(&dxf, &layers));
+        self.status = Some(match written {
+            Ok(()) => format!("{}\nSaved {} and {}", layers[0], svg.display(), dxf.display()),
+            Err(e) => format!("Could not write the section: {}", e),
+        });
+    }
+
+    /// Whether the left button was released close to where it was pressed
+    fn is_click(&self) -> bool {
+        match (self.press_cursor, self.camera.cursor()) {
+            (Some(a), Some(b)) => {
+                let pixels = (b - a).component_mul(&Vec2::new(self.size.width as f32, self.size.height as f32)) / 2.0;
+                pixels.magnitude() <= CLICK_TOLERANCE
+            },
             _ => false,
         }
+    }
+
+    /// Turns to the view from `direction`, or from the opposite side with Ctrl held
+    fn standard_view(&mut self, direction: Vec3) {
+        self.turn_to_view(if self.modifiers.ctrl() { -direction } else { direction });
+    }
+
+    fn turn_to_view(&mut self, direction: Vec3) {
+        self.camera.turn_to(camera::view_orientation(&direction));
+        self.status = Some(format!("View: {}", viewcube::view_name(&direction)));
+    }
+
+    /// Turns to the view clicked on the view cube, or else adds the point under the cursor to the
+    /// measurement in progress, completing it once there are enough
+    fn click(&mut self) {
+        if let Some(direction) = self.view_cube.pick(&self.camera, self.size.height as f32) {
+            self.turn_to_view(direction);
+            return;
+        }
+        let tool = match self.measure_tool {
+            Some(tool) => tool,
+            None => return,
+        };
+        let pick = match self.pick() {
+            Some(pick) => pick,
+            None => return,
+        };
+        self.picks.push(pick);
+        if self.picks.len() < tool.picks_needed() {
+            self.status = Some(format!("{} ({}/{})", tool.hint(), self.picks.len(), tool.picks_needed()));
+            return;
+        }
+        self.status = Some(match Measurement::new(tool, &self.picks) {
+            Some(measurement) => {
+                let text = measurement.describe(self.data.units);
+                self.measurements.push(measurement);
+                text
+            },
+            None => "The points are in a line, pick three points around the edge".to_string(),
+        });
+        self.picks.clear();
+    }
 
+    /// Surface point under the cursor, looking through hidden shells and the part cut away by the section
+    fn pick(&mut self) -> Option<Pick> {
+        let cursor = self.camera.cursor()?;
+        let hidden: Vec<std::ops::Range<u32>> = self.shells.iter().zip(&self.hidden_shells)
+            .filter(|(_, hidden)| **hidden)
+            .map(|(shell, _)| shell.indices.clone())
+            .collect();
+        self.picker.as_mut()?.pick(&self.camera, cursor, self.settings.section.as_ref(), &hidden)
+    }
+
+    /// Queues the markers, lines and labels of every measurement for drawing, and the rotation pivot
+    fn queue_measurements(&mut self) {
+        let color = self.options.theme.get_values().get_text_color();
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let camera = &self.camera;
+        let screen = |p: &Vec3| camera.to_screen(p).xy();
+
+        for measurement in &self.measurements {
+            for (a, b) in measurement.lines() {
+                self.annotations.line(screen(&a), screen(&b), color);
+            }
+            for p in measurement.points() {
+                self.annotations.marker(screen(&p), color);
+            }
+            let label = screen(&measurement.label_position());
+            let pixels = ((label.x + 1.0) / 2.0 * width, (1.0 - label.y) / 2.0 * height);
+            self.overlay.queue(Anchor::At(pixels.0 + 8.0, pixels.1 + 4.0), measurement.describe(self.data.units));
+        }
+        for pick in &self.picks {
+            self.annotations.marker(screen(&pick.point), PENDING_COLOR);
+        }
+        if let Some(pivot) = self.camera.pivot() {
+            self.annotations.marker(screen(&pivot), PIVOT_COLOR);
+        }
+    }
+
+    /// Draws the X, Y and Z axes, as currently oriented, in the bottom right corner. The arms are
+    /// drawn back to front, since the annotations aren't depth tested.
+    fn queue_axis_triad(&mut self) {
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let to_ndc = |x: f32, y: f32| Vec2::new(x / width * 2.0 - 1.0, 1.0 - y / height * 2.0);
+        let origin = (width - TRIAD_SIZE * 2.0, height - TRIAD_SIZE * 2.0);
+        let orientation = self.camera.model_orientation();
+        let mut arms: Vec<(usize, Vec3)> = [Vec3::x(), Vec3::y(), Vec3::z()].iter()
+            .map(|axis| glm::quat_rotate_vec3(&orientation, axis))
+            .enumerate()
+            .collect();
+        //depth is reversed, so the arm pointing out of the screen has the largest z
+        arms.sort_by(|a, b| a.1.z.total_cmp(&b.1.z));
+        for (i, arm) in arms {
+            let tip = (origin.0 + arm.x * TRIAD_SIZE, origin.1 - arm.y * TRIAD_SIZE);
+            self.annotations.line(to_ndc(origin.0, origin.1), to_ndc(tip.0, tip.1), TRIAD_COLORS[i]);
+            let label = (origin.0 + arm.x * TRIAD_SIZE * 1.3 - 4.0, origin.1 - arm.y * TRIAD_SIZE * 1.3 - 8.0);
+            self.overlay.queue(Anchor::At(label.0, label.1), ["X", "Y", "Z"][i].to_string());
+        }
+    }
+
+    /// Step the shell selection forwards or backwards. Stepping past either end clears the selection.
+    fn select_shell(&mut self, step: i64) {
+        let count = self.shells.len() as i64;
+        let current = self.settings.selected_shell.map_or(-1, |s| s as i64);
+        let next = match current + step {
+            n if n < -1 => count - 1,
+            n if n >= count => -1,
+            n => n,
+        };
+        self.settings.selected_shell = if next < 0 { None } else { Some(next as u32) };
+    }
+
+    fn toggle_selected_shell(&mut self) {
+        if let Some(selected) = self.settings.selected_shell {
+            let hidden = &mut self.hidden_shells[selected as usize];
+            *hidden = !*hidden;
+            self.update_draw_ranges();
+        }
+    }
+
+    fn update_draw_ranges(&mut self) {
+        let ranges = self.shells.iter().zip(&self.hidden_shells)
+            .filter(|(_, hidden)| !**hidden)
+            .map(|(shell, _)| shell.indices.clone())
+            .collect();
+        if let Some(model) = &mut self.model {
+            model.set_draw_ranges(ranges);
+        }
+    }
+
+    /// Writes the selected shell, or every shell if none is selected, to its own STL file
+    fn export_shells(&mut self) {
+        let selected: Vec<usize> = match self.settings.selected_shell {
+            Some(s) => vec![s as usize],
+            None => (0..self.shells.len()).collect(),
+        };
+        for i in selected.iter() {
+            let path = shells::export_path(&self.options.filename, *i);
+            if let Err(e) = self.shells[*i].export(&self.data, &path) {
+                self.status = Some(format!("Could not write {}: {}", path.display(), e));
+                return;
+            }
+        }
+        self.status = Some(format!("Exported {} shell(s) next to {}", selected.len(), self.options.filename));
+    }
+
+    fn shell_list(&self) -> String {
+        let mut lines = vec![format!("Shells: {}", self.shells.len())];
+        //keep the selection in view when there are more shells than fit on screen
+        let first = self.settings.selected_shell
+            .map_or(0, |s| (s as usize).saturating_sub(SHELL_LIST_LENGTH / 2))
+            .min(self.shells.len().saturating_sub(SHELL_LIST_LENGTH));
+        for (i, shell) in self.shells.iter().enumerate().skip(first).take(SHELL_LIST_LENGTH) {
+            let marker = if self.settings.selected_shell == Some(i as u32) { '>' } else { ' ' };
+            let hidden = if self.hidden_shells[i] { " (hidden)" } else { "" };
+            lines.push(format!("{}{:>4} {}{}", marker, i, shell.describe(), hidden));
+        }
+        lines.join("\n")
+    }
+
+    /// Runs the mesh repair pipeline on the loaded model and uploads the result
+    pub fn repair(&mut self) {
+        let summary = self.data.repair();
+        if !summary.is_empty() {
+            self.rebuild_model();
+        }
+        self.status = Some(format!("Repair: {}", summary));
+    }
+
+    /// Recreates the GPU buffers after the model data has been modified
+    fn rebuild_model(&mut self) {
+        let (shells, vertex_shells) = shells::find_shells(&mut self.data);
+        let attributes: Vec<VertexAttributes> = vertex_shells.into_iter()
+            .map(|shell| VertexAttributes { shell, thickness: thickness::NO_THICKNESS, curvature: [0.0; 2] })
+            .collect();
+
+        self.model = Some(Model::new(&self.device, &self.config, &self.options.theme, self.options.sample_count,
+            &self.data, attributes.as_slice(), &ShadingTextures { environment: &self.environment, matcap: &self.matcap_texture }));
+        self.attributes = attributes;
+        self.thickness = None;
+        self.curvature = None;
+        self.picker = Some(Picker::new(&self.data));
+        self.picks.clear();
+        self.settings.section = self.settings.section.and_then(|s| s.snapped_axis())
+            .map(|axis| SectionPlane::axis(axis, &self.data.bounds));
+        if matches!(self.settings.shading, Shading::Thickness | Shading::MeanCurvature | Shading::GaussianCurvature) {
+            self.settings.shading = Shading::Theme;
+        }
+        self.hidden_shells = vec![false; shells.len()];
+        self.shells = shells;
+        self.settings.selected_shell = None;
+        self.show_intersections = false;
+        self.info = ModelInfo::new(&self.data, self.options.density);
+        self.prepare_edges();
+        self.update_feature_edges();
+        self.update_overhangs();
     }
 
 }
\ No newline at end of file
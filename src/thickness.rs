@@ -0,0 +1,150 @@
+use std::fmt;
+use nalgebra_glm as glm;
+use glm::Vec3;
+use crate::bvh::Bvh;
+use crate::loader::{worker_count, ModelBounds, ModelData};
+use crate::repair::{weld_map, weld_tolerance};
+use crate::units::Units;
+
+/// Thinnest wall considered printable when none is given, in millimetres
+pub const DEFAULT_MIN_THICKNESS: f32 = 0.8;
+
+/// Thickness of vertices whose inward ray leaves the model without hitting anything, e.g. on open meshes
+pub const NO_THICKNESS: f32 = -1.0;
+
+/// Summary of a thickness analysis against a minimum printable thickness
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThicknessReport {
+    pub min_thickness: f32,
+    /// Thinnest wall found, if any ray hit the opposite side
+    pub thinnest: Option<f32>,
+    pub thin_vertices: usize,
+    pub vertices: usize,
+    pub units: Units,
+}
+
+impl ThicknessReport {
+    pub fn new(thickness: &[f32], min_thickness: f32, units: Units) -> Self {
+        let measured = thickness.iter().copied().filter(|t| *t >= 0.0);
+        Self {
+            min_thickness,
+            thinnest: measured.clone().reduce(f32::min),
+            thin_vertices: measured.filter(|t| *t < min_thickness).count(),
+            vertices: thickness.len(),
+            units,
+        }
+    }
+}
+
+impl fmt::Display for ThicknessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.thinnest {
+            Some(t) => write!(f, "Thinnest wall: {:.3} {}, ", t, self.units)?,
+            None => write!(f, "No walls measured, ")?,
+        }
+        write!(f, "{} of {} vertices below {:.2} {}", self.thin_vertices, self.vertices, self.min_thickness, self.units)
+    }
+}
+
+/// Measures the wall thickness at every vertex. A ray is cast inwards from the centre of each face,
+/// against its normal, to the nearest other triangle it hits, and each vertex takes the thinnest of
+/// the faces around it. Face normals cross a wall squarely, where a vertex normal would run
+/// diagonally through corners and edges and overstate their thickness. Vertices where every ray
+/// escapes get [`NO_THICKNESS`].
+///
+/// The work is split between worker threads like the loader does. The mesh is expected to face
+/// outwards; see [`ModelData::repair`].
+pub fn wall_thickness(data: &ModelData, max_workers: Option<usize>) -> Vec<f32> {
+    let tolerance = weld_tolerance(&ModelBounds::from_vertices(&data.vertices));
+    let welded = weld_map(&data.vertices, tolerance);
+    let corners: Vec<[u32; 3]> = data.indices.chunks_exact(3)
+        .map(|t| [welded[t[0] as usize], welded[t[1] as usize], welded[t[2] as usize]])
+        .collect();
+
+    let bvh = Bvh::new(&data.vertices, &data.indices);
+    let faces: Vec<u32> = (0..corners.len() as u32).collect();
+    let num_workers = worker_count(max_workers).max(1);
+    let per_worker = faces.len() / num_workers + 1;
+
+    let (bvh, corners) = (&bvh, corners.as_slice());
+    let measured: Vec<(u32, f32)> = crossbeam::scope(|s| {
+        let handles: Vec<crossbeam::thread::ScopedJoinHandle<_>> = faces.chunks(per_worker).map(|chunk| {
+            s.spawn(move |_| {
+                chunk.iter().filter_map(|f| {
+                    let p = corners[*f as usize].map(|i| Vec3::from(data.vertices[i as usize].pos));
+                    let normal = (p[1] - p[0]).cross(&(p[2] - p[0]));
+                    if normal.magnitude_squared() == 0.0 {
+                        return None;
+                    }
+                    let origin = (p[0] + p[1] + p[2]) / 3.0;
+                    let hit = bvh.cast_ray(&origin, &-normal.normalize(), tolerance, |t| t == *f);
+                    hit.map(|h| (*f, h.distance))
+                }).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    }).unwrap();
+
+    let mut thickness = vec![NO_THICKNESS; data.vertices.len()];
+    for (f, t) in measured {
+        for v in corners[f as usize] {
+            let current = &mut thickness[v as usize];
+            if *current == NO_THICKNESS || t < *current {
+                *current = t;
+            }
+        }
+    }
+    for (i, w) in welded.iter().enumerate() {
+        thickness[i] = thickness[*w as usize];
+    }
+    thickness
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use nalgebra_glm::Vec3;
+    use crate::bvh::ray_triangle;
+    use crate::loader::{Loader, ModelData, Vertex};
+    use crate::units::Units;
+    use super::{wall_thickness, ThicknessReport, NO_THICKNESS};
+
+    fn cube() -> ModelData {
+        Loader::new("assets/cube.stl".to_string(), SystemTime::now(), Some(1)).run()
+    }
+
+    #[test]
+    fn test_ray_triangle() {
+        let tri = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0)];
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        assert_eq!(ray_triangle(&Vec3::new(0.5, 0.5, 3.0), &down, &tri), Some(3.0));
+        assert_eq!(ray_triangle(&Vec3::new(0.5, 0.5, -3.0), &down, &tri), Some(-3.0));
+        assert_eq!(ray_triangle(&Vec3::new(1.5, 1.5, 3.0), &down, &tri), None);
+        assert_eq!(ray_triangle(&Vec3::new(0.5, 0.5, 3.0), &Vec3::new(1.0, 0.0, 0.0), &tri), None);
+    }
+
+    #[test]
+    fn test_cube_corners() {
+        //every vertex of the cube is a corner, which should still measure the 20 mm between opposite faces
+        let thickness = wall_thickness(&cube(), Some(2));
+        assert!(thickness.iter().all(|t| (t - 20.0).abs() < 1e-3), "{:?}", thickness);
+
+        let report = ThicknessReport::new(&thickness, 25.0, Units::Millimetres);
+        assert_eq!(report.thin_vertices, thickness.len());
+        assert!((report.thinnest.unwrap() - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_open_surface() {
+        //a single triangle has nothing on its far side
+        let data = ModelData {
+            vertices: vec![Vertex { pos: [0.0, 0.0, 0.0] }, Vertex { pos: [1.0, 0.0, 0.0] }, Vertex { pos: [0.0, 1.0, 0.0] }],
+            indices: vec![0, 1, 2],
+            ..cube()
+        };
+        let thickness = wall_thickness(&data, Some(1));
+        assert_eq!(thickness, vec![NO_THICKNESS; 3]);
+        assert_eq!(ThicknessReport::new(&thickness, 1.0, Units::Millimetres).thinnest, None);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::str::FromStr;
+use nalgebra_glm as glm;
+use glm::{Quat, Vec3};
+use crate::loader::ModelBounds;
+
+/// Models less than this many units across are taken to be in metres. Parts of a metre or less
+/// are common, while a part under a millimetre across is not.
+const METRE_DETECTION_SIZE: f32 = 1.0;
+
+/// Length unit the coordinates of a model are in. STL files don't say, so it is guessed from the
+/// size of the model unless given.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum Units {
+    #[serde(rename = "mm")]
+    Millimetres,
+    #[serde(rename = "cm")]
+    Centimetres,
+    #[serde(rename = "m")]
+    Metres,
+    #[serde(rename = "in")]
+    Inches,
+}
+
+impl Units {
+    /// Millimetres in one unit
+    pub fn millimetres(&self) -> f32 {
+        match self {
+            Units::Millimetres => 1.0,
+            Units::Centimetres => 10.0,
+            Units::Metres => 1000.0,
+            Units::Inches => 25.4,
+        }
+    }
+
+    /// Converts a length in millimetres, e.g. a printer's build volume, into these units
+    pub fn millimetres_to_units(&self, length: f32) -> f32 {
+        length / self.millimetres()
+    }
+
+    /// Millimetres unless the model is so small that it must be in metres. Centimetres and inches
+    /// can't be told from millimetres by size alone and have to be given.
+    pub fn detect(bounds: &ModelBounds) -> Self {
+        let size = (bounds.x.1 - bounds.x.0).max(bounds.y.1 - bounds.y.0).max(bounds.z.1 - bounds.z.0);
+        if size > 0.0 && size < METRE_DETECTION_SIZE {
+            Units::Metres
+        } else {
+            Units::Millimetres
+        }
+    }
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mm" => Ok(Units::Millimetres),
+            "cm" => Ok(Units::Centimetres),
+            "m" => Ok(Units::Metres),
+            "in" | "inch" | "inches" => Ok(Units::Inches),
+            _ => Err(format!("Unknown units '{}', expected one of mm, cm, m, in", s)),
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Units::Millimetres => "mm",
+            Units::Centimetres => "cm",
+            Units::Metres => "m",
+            Units::Inches => "in",
+        })
+    }
+}
+
+/// Axis of a model pointing up, away from the build plate. Also the direction overhangs are found for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum BuildDirection {
+    #[serde(rename = "+X")]
+    PosX,
+    #[serde(rename = "-X")]
+    NegX,
+    #[serde(rename = "+Y")]
+    PosY,
+    #[serde(rename = "-Y")]
+    NegY,
+    #[serde(rename = "+Z")]
+    PosZ,
+    #[serde(rename = "-Z")]
+    NegZ,
+}
+
+impl BuildDirection {
+    pub fn vector(&self) -> [f32; 3] {
+        match self {
+            BuildDirection::PosX => [1.0, 0.0, 0.0],
+            BuildDirection::NegX => [-1.0, 0.0, 0.0],
+            BuildDirection::PosY => [0.0, 1.0, 0.0],
+            BuildDirection::NegY => [0.0, -1.0, 0.0],
+            BuildDirection::PosZ => [0.0, 0.0, 1.0],
+            BuildDirection::NegZ => [0.0, 0.0, -1.0],
+        }
+    }
+
+    /// Index of the axis the direction lies along
+    pub fn axis(&self) -> usize {
+        match self {
+            BuildDirection::PosX | BuildDirection::NegX => 0,
+            BuildDirection::PosY | BuildDirection::NegY => 1,
+            BuildDirection::PosZ | BuildDirection::NegZ => 2,
+        }
+    }
+
+    /// Rotation that turns this direction up, to +Z. Sideways directions tip over about X or Y, so
+    /// that the front of a Y-up model, facing +Z, ends up facing the front view.
+    pub fn up_rotation(&self) -> Quat {
+        let turn = |angle: f32, axis: Vec3| glm::quat_angle_axis(angle.to_radians(), &axis);
+        match self {
+            BuildDirection::PosX => turn(-90.0, Vec3::y()),
+            BuildDirection::NegX => turn(90.0, Vec3::y()),
+            BuildDirection::PosY => turn(90.0, Vec3::x()),
+            BuildDirection::NegY => turn(-90.0, Vec3::x()),
+            BuildDirection::PosZ => Quat::identity(),
+            BuildDirection::NegZ => turn(180.0, Vec3::x()),
+        }
+    }
+
+    /// Cycles through every direction, used by the viewer
+    pub fn next(&self) -> Self {
+        match self {
+            BuildDirection::PosZ => BuildDirection::NegZ,
+            BuildDirection::NegZ => BuildDirection::PosX,
+            BuildDirection::PosX => BuildDirection::NegX,
+            BuildDirection::NegX => BuildDirection::PosY,
+            BuildDirection::PosY => BuildDirection::NegY,
+            BuildDirection::NegY => BuildDirection::PosZ,
+        }
+    }
+}
+
+impl FromStr for BuildDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "x" | "+x" => Ok(BuildDirection::PosX),
+            "-x" => Ok(BuildDirection::NegX),
+            "y" | "+y" => Ok(BuildDirection::PosY),
+            "-y" => Ok(BuildDirection::NegY),
+            "z" | "+z" => Ok(BuildDirection::PosZ),
+            "-z" => Ok(BuildDirection::NegZ),
+            _ => Err(format!("Unknown build direction '{}', expected one of x, -x, y, -y, z, -z", s)),
+        }
+    }
+}
+
+impl fmt::Display for BuildDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BuildDirection::PosX => "+X",
+            BuildDirection::NegX => "-X",
+            BuildDirection::PosY => "+Y",
+            BuildDirection::NegY => "-Y",
+            BuildDirection::PosZ => "+Z",
+            BuildDirection::NegZ => "-Z",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra_glm as glm;
+    use glm::Vec3;
+    use crate::loader::ModelBounds;
+    use super::{BuildDirection, Units};
+
+    #[test]
+    fn test_detect_units() {
+        let bounds = |size: f32| ModelBounds { x: (0.0, size), y: (-size / 4.0, size / 4.0), z: (5.0, 5.0 + size / 2.0) };
+        assert_eq!(Units::detect(&bounds(120.0)), Units::Millimetres);
+        assert_eq!(Units::detect(&bounds(0.12)), Units::Metres);
+        assert_eq!(Units::detect(&bounds(0.0)), Units::Millimetres);
+        assert_eq!("inch".parse::<Units>(), Ok(Units::Inches));
+        assert!((Units::Inches.millimetres_to_units(254.0) - 10.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_parse_direction() {
+        assert_eq!("z".parse::<BuildDirection>(), Ok(BuildDirection::PosZ));
+        assert_eq!("-Y".parse::<BuildDirection>(), Ok(BuildDirection::NegY));
+        assert!("w".parse::<BuildDirection>().is_err());
+    }
+
+    #[test]
+    fn test_up_rotation() {
+        for direction in ["z", "-z", "x", "-x", "y", "-y"] {
+            let direction: BuildDirection = direction.parse().unwrap();
+            let up = glm::quat_rotate_vec3(&direction.up_rotation(), &Vec3::from(direction.vector()));
+            assert!((up - Vec3::z()).magnitude() < 1e-6, "{} {:?}", direction, up);
+        }
+        //a Y-up model faces +Z, which should end up facing the viewer of the front view
+        let front = glm::quat_rotate_vec3(&BuildDirection::PosY.up_rotation(), &Vec3::z());
+        assert!((front + Vec3::y()).magnitude() < 1e-6, "{:?}", front);
+    }
+}
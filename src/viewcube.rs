@@ -0,0 +1,379 @@
+use wgpu::util::DeviceExt;
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3, Mat4, Quat};
+use crate::camera::Camera;
+use crate::color::Theme;
+
+/// Width and height of the view cube's corner of the window, in pixels
+const CUBE_PIXELS: f32 = 128.0;
+/// Distance of the view cube from the top right corner of the window, in pixels
+const MARGIN: f32 = 12.0;
+/// Half the size of the cube in its viewport, small enough that it fits when seen from a corner
+const CUBE_SCALE: f32 = 0.55;
+/// Face coordinates beyond this belong to an edge or corner of the cube rather than the face
+const FACE_EXTENT: f32 = 0.6;
+
+/// Names of the standard views along +X, +Y and +Z, and along -X, -Y and -Z
+const VIEW_NAMES: [[&str; 3]; 2] = [["Right", "Back", "Top"], ["Left", "Front", "Bottom"]];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CubeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    /// Direction of the view the cell snaps to, with every component -1, 0 or 1
+    region: [f32; 3],
+}
+
+/// Mirrors `CubeSettings` in viewcube.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CubeUniform {
+    to_clip: [f32; 16],
+    color: [f32; 4],
+    /// Region under the cursor, with w = 1 if there is one
+    hovered: [f32; 4],
+}
+
+/// Name of the standard view from `direction`, e.g. "Front Right Top" for a corner
+pub fn view_name(direction: &Vec3) -> String {
+    //sorted front to back, then left to right, then top to bottom
+    [1, 0, 2].iter()
+        .filter(|i| direction[**i] != 0.0)
+        .map(|i| VIEW_NAMES[(direction[*i] < 0.0) as usize][*i])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The region of the cube hit at a point of its viewport, in normalized device coordinates, as the
+/// direction of the view it snaps to. Faces snap to the six views along the axes, edges and corners
+/// to the views between them.
+pub fn region_at(orientation: &Quat, point: Vec2) -> Option<Vec3> {
+    //a ray into the screen, taken back into the cube's own coordinates
+    let back = glm::quat_conjugate(orientation);
+    let origin = glm::quat_rotate_vec3(&back, &Vec3::new(point.x / CUBE_SCALE, point.y / CUBE_SCALE, 2.0));
+    let direction = glm::quat_rotate_vec3(&back, &Vec3::new(0.0, 0.0, -1.0));
+    let (mut near, mut far) = (f32::NEG_INFINITY, f32::INFINITY);
+    for i in 0..3 {
+        if direction[i].abs() < 1e-9 {
+            if origin[i].abs() > 1.0 {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((-1.0 - origin[i]) / direction[i], (1.0 - origin[i]) / direction[i]);
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+    if near > far {
+        return None;
+    }
+    let hit = origin + direction * near;
+    Some(hit.map(|c| if c > FACE_EXTENT { 1.0 } else if c < -FACE_EXTENT { -1.0 } else { 0.0 }))
+}
+
+/// Triangles of the cube [-1, 1]³, with each face split into a 3×3 grid of cells for its centre,
+/// edges and corners. Faces wind counter-clockwise seen from outside.
+fn cube_vertices() -> Vec<CubeVertex> {
+    let bounds = [-1.0, -FACE_EXTENT, FACE_EXTENT, 1.0];
+    let mut vertices = Vec::with_capacity(6 * 9 * 6);
+    for axis in 0..3 {
+        for sign in [1.0f32, -1.0] {
+            let normal = Vec3::ith(axis, sign);
+            //u × v = normal, so that the quads below wind the right way
+            let (mut u, mut v) = (Vec3::ith((axis + 1) % 3, 1.0), Vec3::ith((axis + 2) % 3, 1.0));
+            if sign < 0.0 {
+                std::mem::swap(&mut u, &mut v);
+            }
+            for i in 0..3 {
+                for j in 0..3 {
+                    let region = normal + u * (i as f32 - 1.0) + v * (j as f32 - 1.0);
+                    let corner = |a: usize, b: usize| CubeVertex {
+                        position: (normal + u * bounds[a] + v * bounds[b]).into(),
+                        normal: normal.into(),
+                        region: region.into(),
+                    };
+                    let quad = [corner(i, j), corner(i + 1, j), corner(i + 1, j + 1), corner(i, j + 1)];
+                    vertices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                }
+            }
+        }
+    }
+    vertices
+}
+
+/// A cube in the top right corner of the window, turned like the model, that snaps the camera to
+/// the view from a face, edge or corner when clicked. Drawn on top of the scene like the annotations.
+pub struct ViewCube {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    color: [f32; 4],
+    width: f32,
+}
+
+impl ViewCube {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, theme: &Theme) -> Self {
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("View cube shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("viewcube.wgsl").into()),
+            }
+        );
+
+        let uniform_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("View cube uniform buffer"),
+                size: std::mem::size_of::<CubeUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }
+        );
+        let vertices = cube_vertices();
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("View cube vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None
+                    },
+                ],
+                label: Some("View cube bind group layout"),
+            }
+        );
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("View cube bind group"),
+            }
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("View cube pipeline layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let f32x3 = std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress;
+        let vertex_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<CubeVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                //position
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                //normal
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: f32x3,
+                    shader_location: 1,
+                },
+                //region
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: f32x3 * 2,
+                    shader_location: 2,
+                },
+            ]
+        };
+
+        //the cube is convex, so culling the faces turned away is enough without a depth buffer
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("View cube pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[vertex_buf_layout],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }
+        );
+
+        Self {
+            render_pipeline,
+            bind_group,
+            uniform_buffer,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            color: theme.get_values().get_text_color(),
+            width: config.width as f32,
+        }
+    }
+
+    pub fn set_size(&mut self, width: u32) {
+        self.width = width as f32;
+    }
+
+    /// Top left corner of the cube's viewport, in pixels
+    fn corner(&self) -> (f32, f32) {
+        (self.width - MARGIN - CUBE_PIXELS, MARGIN)
+    }
+
+    /// Position in the cube's viewport, in normalized device coordinates, of the camera's cursor
+    fn cursor(&self, camera: &Camera, height: f32) -> Option<Vec2> {
+        let cursor = camera.cursor()?;
+        let (x, y) = self.corner();
+        let pixels = ((cursor.x + 1.0) / 2.0 * self.width - x, (1.0 - cursor.y) / 2.0 * height - y);
+        let point = Vec2::new(pixels.0 / CUBE_PIXELS * 2.0 - 1.0, 1.0 - pixels.1 / CUBE_PIXELS * 2.0);
+        (point.x.abs() <= 1.0 && point.y.abs() <= 1.0).then_some(point)
+    }
+
+    /// Direction of the view under the cursor, if it is over the cube
+    pub fn pick(&self, camera: &Camera, height: f32) -> Option<Vec3> {
+        region_at(&camera.orientation(), self.cursor(camera, height)?)
+    }
+
+    /// Face names and the pixel positions of the faces' centres, for the faces turned towards the viewer
+    pub fn labels(&self, orientation: &Quat) -> Vec<((f32, f32), &'static str)> {
+        let (x, y) = self.corner();
+        (0..6).filter_map(|i| {
+            let direction = Vec3::ith(i % 3, if i < 3 { 1.0 } else { -1.0 });
+            let center = glm::quat_rotate_vec3(orientation, &direction) * CUBE_SCALE;
+            (center.z > 0.3 * CUBE_SCALE).then(|| {
+                ((x + (center.x + 1.0) / 2.0 * CUBE_PIXELS, y + (1.0 - center.y) / 2.0 * CUBE_PIXELS), VIEW_NAMES[i / 3][i % 3])
+            })
+        }).collect()
+    }
+
+    pub fn draw(&self, camera: &Camera, frame: &wgpu::SurfaceTexture, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, height: f32) {
+        //the viewport has to fit in the window
+        let (x, y) = self.corner();
+        if x < 0.0 || y + CUBE_PIXELS > height {
+            return;
+        }
+        let i = Mat4::identity();
+        let to_clip = glm::translate(&i, &Vec3::new(0.0, 0.0, 0.5))
+            * glm::scale(&i, &Vec3::new(CUBE_SCALE, CUBE_SCALE, 0.25))
+            * glm::quat_to_mat4(&camera.orientation());
+        let mut uniform = CubeUniform {
+            to_clip: [0.0; 16],
+            color: self.color,
+            hovered: [0.0; 4],
+        };
+        uniform.to_clip.copy_from_slice(to_clip.as_slice());
+        if let Some(region) = self.pick(camera, height) {
+            uniform.hovered = [region.x, region.y, region.z, 1.0];
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("View cube render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: None,
+            }
+        );
+        render_pass.set_viewport(x, y, CUBE_PIXELS, CUBE_PIXELS, 0.0, 1.0);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra_glm as glm;
+    use glm::{Vec2, Vec3};
+    use crate::camera::view_orientation;
+    use super::{cube_vertices, region_at, view_name};
+
+    #[test]
+    fn test_view_orientation() {
+        //the front view is the one the camera used to start with, a quarter turn about X
+        let front = view_orientation(&Vec3::new(0.0, -1.0, 0.0));
+        let old = glm::quat_rotate_normalized_axis(&glm::quat_identity(), -std::f32::consts::FRAC_PI_2, &Vec3::x_axis());
+        assert!(glm::quat_dot(&front, &old).abs() > 0.9999);
+
+        //every view looks at the model from its direction, and keeps Z up unless looking along it
+        for direction in [Vec3::new(1.0, -1.0, 1.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, -1.0)] {
+            let q = view_orientation(&direction);
+            let towards_viewer = glm::quat_rotate_vec3(&q, &direction.normalize());
+            assert!((towards_viewer - Vec3::z()).magnitude() < 1e-5);
+            let up = glm::quat_rotate_vec3(&q, &Vec3::z());
+            assert!(up.x.abs() < 1e-5 && (direction.z.abs() == 1.0 || up.y > 0.0));
+        }
+        let top_up = glm::quat_rotate_vec3(&view_orientation(&Vec3::z()), &Vec3::y());
+        assert!((top_up - Vec3::y()).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn test_region_at() {
+        let front = view_orientation(&Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(region_at(&front, Vec2::new(0.0, 0.0)), Some(Vec3::new(0.0, -1.0, 0.0)));
+        assert_eq!(region_at(&front, Vec2::new(0.5, 0.5)), Some(Vec3::new(1.0, -1.0, 1.0)));
+        assert_eq!(region_at(&front, Vec2::new(0.0, -0.5)), Some(Vec3::new(0.0, -1.0, -1.0)));
+        assert_eq!(region_at(&front, Vec2::new(0.8, 0.0)), None);
+
+        //seen from a corner, the middle of the view is that corner
+        let corner = Vec3::new(-1.0, 1.0, 1.0);
+        assert_eq!(region_at(&view_orientation(&corner), Vec2::zeros()), Some(corner));
+    }
+
+    #[test]
+    fn test_cube_vertices() {
+        //every triangle faces outwards, the way its normal points
+        for t in cube_vertices().chunks_exact(3) {
+            let p = t.iter().map(|v| Vec3::from(v.position)).collect::<Vec<_>>();
+            let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+            assert!(n.dot(&Vec3::from(t[0].normal)) > 0.0);
+        }
+        assert_eq!(view_name(&Vec3::new(1.0, -1.0, 1.0)), "Front Right Top");
+        assert_eq!(view_name(&Vec3::new(0.0, 0.0, -1.0)), "Bottom");
+    }
+}
@@ -0,0 +1,45 @@
+//the view cube, drawn semi-transparent in its own corner of the window
+
+struct CubeSettings {
+    to_clip: mat4x4<f32>;
+    color: vec4<f32>;
+    hovered: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> cube: CubeSettings;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] region: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] facing: f32;
+    [[location(1)]] region: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = cube.to_clip * vec4<f32>(in.position, 1.0);
+    out.facing = normalize((cube.to_clip * vec4<f32>(in.normal, 0.0)).xyz).z;
+    out.region = in.region;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    //edges and corners are a little more opaque than the faces, so they read as separate targets
+    let cells = abs(in.region);
+    var alpha = 0.15 + 0.2 * in.facing;
+    if (cells.x + cells.y + cells.z > 1.5) {
+        alpha = alpha + 0.1;
+    }
+    if (cube.hovered.w > 0.5 && all(abs(in.region - cube.hovered.xyz) < vec3<f32>(0.5))) {
+        return vec4<f32>(1.0, 0.45, 0.1, 0.8);
+    }
+    return vec4<f32>(cube.color.rgb, alpha);
+}
@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3};
+use crate::loader::{ModelData, Vertex};
+use crate::slice::Layer;
+use crate::units::Units;
+
+/// Header written at the start of every binary STL we produce. It must not begin with "solid",
+/// otherwise the loader (and most other tools) will try to parse the file as ASCII.
+const HEADER: &[u8] = b"hustl binary STL";
+
+/// Writes the model as a binary STL file. Facet normals are recomputed from the triangle winding.
+pub fn write_binary_stl<P: AsRef<Path>>(path: P, data: &ModelData) -> io::Result<()> {
+    write_triangles(path, &data.vertices, data.indices.chunks_exact(3))
+}
+
+/// Writes an arbitrary subset of triangles that index into `vertices` as a binary STL file.
+pub fn write_triangles<'a, P, T>(path: P, vertices: &[Vertex], triangles: T) -> io::Result<()>
+    where P: AsRef<Path>, T: Iterator<Item = &'a [u32]> + Clone
+{
+    let mut out = BufWriter::new(File::create(path)?);
+
+    let mut header = [0u8; 80];
+    header[..HEADER.len()].copy_from_slice(HEADER);
+    out.write_all(&header)?;
+    out.write_all(&(triangles.clone().count() as u32).to_le_bytes())?;
+
+    for tri in triangles {
+        let p = [0, 1, 2].map(|i| Vec3::from(vertices[tri[i] as usize].pos));
+        let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        let n = if n.magnitude() > 0.0 { n.normalize() } else { n };
+
+        for val in n.iter().chain(p.iter().flat_map(|v| v.iter())) {
+            out.write_all(&val.to_le_bytes())?;
+        }
+        //attribute byte count, unused
+        out.write_all(&[0u8; 2])?;
+    }
+    out.flush()
+}
+
+/// Margin around the drawing in SVG files, in millimetres
+const SVG_MARGIN: f32 = 1.0;
+
+/// Width of the contour lines in SVG files, in millimetres
+const SVG_STROKE: f32 = 0.1;
+
+/// Writes the contours as an SVG drawing sized in millimetres, with one group per layer. Layers are drawn on top
+/// of each other in their plane's 2D coordinates; SVG's y axis points down, so v is negated.
+pub fn write_svg<P: AsRef<Path>>(path: P, layers: &[Layer]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    let mut min = Vec2::repeat(f32::INFINITY);
+    let mut max = Vec2::repeat(f32::NEG_INFINITY);
+    for p in layers.iter().flat_map(|l| &l.contours).flat_map(|c| &c.points) {
+        let p = Vec2::new(p.x, -p.y);
+        min = min.inf(&p);
+        max = max.sup(&p);
+    }
+    if min.x > max.x {
+        min = Vec2::zeros();
+        max = Vec2::zeros();
+    }
+    let units = layers.first().map_or(Units::Millimetres, |l| l.units);
+    let margin = units.millimetres_to_units(SVG_MARGIN);
+    let min = min - Vec2::repeat(margin);
+    let size = max - min + Vec2::repeat(margin);
+    let millimetres = size * units.millimetres();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}mm" height="{}mm" viewBox="{} {} {} {}">"#,
+        millimetres.x, millimetres.y, min.x, min.y, size.x, size.y)?;
+    for (i, layer) in layers.iter().enumerate() {
+        writeln!(out, r#"  <g id="layer{}" fill="none" stroke="black" stroke-width="{}">"#, i, units.millimetres_to_units(SVG_STROKE))?;
+        writeln!(out, "    <title>{}</title>", layer.plane.position())?;
+        for contour in &layer.contours {
+            let mut d = String::new();
+            for (j, p) in contour.points.iter().enumerate() {
+                d.push_str(&format!("{}{} {} ", if j == 0 { 'M' } else { 'L' }, p.x, -p.y));
+            }
+            if contour.closed {
+                d.push('Z');
+            }
+            writeln!(out, r#"    <path d="{}"/>"#, d.trim_end())?;
+        }
+        writeln!(out, "  </g>")?;
+    }
+    writeln!(out, "</svg>")?;
+    out.flush()
+}
+
+/// Writes the contours as an ASCII DXF (R12) file, with one POLYLINE per contour on a DXF layer per slice.
+/// Points are in their plane's 2D coordinates, with the plane's offset as the elevation.
+pub fn write_dxf<P: AsRef<Path>>(path: P, layers: &[Layer]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    //DXF is a flat list of group code / value pairs, each on its own line
+    let mut pair = |code: u32, value: &dyn std::fmt::Display| writeln!(out, "{}\n{}", code, value);
+
+    pair(0, &"SECTION")?;
+    pair(2, &"ENTITIES")?;
+    for (i, layer) in layers.iter().enumerate() {
+        let name = format!("SLICE{}", i);
+        for contour in &layer.contours {
+            pair(0, &"POLYLINE")?;
+            pair(8, &name)?;
+            pair(66, &1)?;
+            pair(10, &0.0)?;
+            pair(20, &0.0)?;
+            pair(30, &layer.plane.offset)?;
+            pair(70, &(contour.closed as u32))?;
+            for p in &contour.points {
+                pair(0, &"VERTEX")?;
+                pair(8, &name)?;
+                pair(10, &p.x)?;
+                pair(20, &p.y)?;
+                pair(30, &layer.plane.offset)?;
+            }
+            pair(0, &"SEQEND")?;
+            pair(8, &name)?;
+        }
+    }
+    pair(0, &"ENDSEC")?;
+    pair(0, &"EOF")?;
+    out.flush()
+}
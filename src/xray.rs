@@ -0,0 +1,212 @@
+/// Weighted sums of premultiplied colour and alpha. Half floats keep the large weights of near layers.
+pub const ACCUMULATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Product of 1 - alpha over all layers
+pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// A layer target, rendered with MSAA like the scene and resolved for the composite pass to read
+struct LayerTarget {
+    multisampled: Option<wgpu::TextureView>,
+    resolved: wgpu::TextureView,
+}
+
+impl LayerTarget {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, format: wgpu::TextureFormat, label: &str) -> Self {
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        };
+        let resolved = device.create_texture(&desc).create_view(&wgpu::TextureViewDescriptor::default());
+        let multisampled = (sample_count > 1).then(|| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                sample_count,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ..desc
+            }).create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        Self { multisampled, resolved }
+    }
+
+    fn attachment(&self, clear: wgpu::Color) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: self.multisampled.as_ref().unwrap_or(&self.resolved),
+            resolve_target: self.multisampled.as_ref().map(|_| &self.resolved),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                store: true,
+            }
+        }
+    }
+}
+
+/// Targets for X-ray mode, where the model is drawn semi-transparent with weighted blended
+/// order-independent transparency. The model accumulates its layers into these targets in any order,
+/// see `fs_xray` in model.wgsl, and the composite pass blends the average over the scene.
+pub struct XRay {
+    composite_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sample_count: u32,
+    /// Layer targets and the bind group reading them, which depend on the window size
+    targets: (LayerTarget, LayerTarget, wgpu::BindGroup),
+}
+
+impl XRay {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(
+            &wgpu::ShaderModuleDescriptor {
+                label: Some("X-ray shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("xray.wgsl").into()),
+            }
+        );
+
+        let layer_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None
+        };
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[layer_entry(0), layer_entry(1)],
+                label: Some("X-ray bind group layout"),
+            }
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("X-ray pipeline layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        //blends over the scene before it is resolved, so it runs at the scene's sample count
+        let composite_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("X-ray composite pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        let targets = Self::targets(device, config, sample_count, &bind_group_layout);
+
+        Self {
+            composite_pipeline,
+            bind_group_layout,
+            sample_count,
+            targets,
+        }
+    }
+
+    /// Creates the layer targets at the size of the surface, and the bind group reading from them
+    fn targets(device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        layout: &wgpu::BindGroupLayout) -> (LayerTarget, LayerTarget, wgpu::BindGroup) {
+        let accumulation = LayerTarget::new(device, config, sample_count, ACCUMULATION_FORMAT, "X-ray accumulation texture");
+        let revealage = LayerTarget::new(device, config, sample_count, REVEALAGE_FORMAT, "X-ray revealage texture");
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&accumulation.resolved),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&revealage.resolved),
+                    },
+                ],
+                label: Some("X-ray bind group"),
+            }
+        );
+        (accumulation, revealage, bind_group)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.targets = Self::targets(device, config, self.sample_count, &self.bind_group_layout);
+    }
+
+    /// Clears the layer targets and starts the pass the model accumulates into. The depth buffer is
+    /// tested against but left as it is.
+    pub fn begin_accumulation<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, depth: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+        let (accumulation, revealage, _) = &self.targets;
+        encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("X-ray accumulation pass"),
+                color_attachments: &[
+                    accumulation.attachment(wgpu::Color::TRANSPARENT),
+                    revealage.attachment(wgpu::Color::WHITE),
+                ],
+                depth_stencil_attachment: Some (
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: depth,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true
+                        }),
+                        stencil_ops: None,
+                    }
+                )
+            }
+        )
+    }
+
+    /// Blends the accumulated layers over `color`, the scene's colour target before it is resolved
+    pub fn composite(&self, color: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("X-ray composite pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }],
+                depth_stencil_attachment: None,
+            }
+        );
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &self.targets.2, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,27 @@
+//Composites the X-ray layers accumulated by fs_xray in model.wgsl over the frame. The accumulated colour
+//divided by the accumulated alpha is the weighted average of the layers, and the revealage is how much
+//of the background still shows through them.
+
+[[group(0), binding(0)]]
+var accumulation: texture_2d<f32>;
+[[group(0), binding(1)]]
+var revealage: texture_2d<f32>;
+
+//a single triangle covering the screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let x = f32(index & 1u) * 4.0 - 1.0;
+    let y = f32(index >> 1u) * 4.0 - 1.0;
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let revealed = textureLoad(revealage, pixel, 0).r;
+    if (revealed > 0.999) {
+        discard;
+    }
+    let sum = textureLoad(accumulation, pixel, 0);
+    return vec4<f32>(sum.rgb / max(sum.a, 0.00001), 1.0 - revealed);
+}